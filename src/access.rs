@@ -0,0 +1,145 @@
+// Copyright 2019 The Psyche Authors
+// This file is part of Psyche.
+//
+// Psyche is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Psyche is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Psyche. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::collections::HashSet;
+use ethereum_types::Address;
+use serde_json::Value;
+use crate::block::parse_u256;
+use crate::U256;
+
+/// Addresses and storage slots accessed during a transaction, accessing a
+/// warm entry is cheaper than a cold one (EIP-2929)
+pub struct AccessSet {
+    addresses: HashSet<Address>,
+    slots: HashSet<(Address, U256)>,
+    /// Entries warmed by the executing frames, to revert them
+    journal: Vec<(Address, Option<U256>)>,
+}
+
+impl AccessSet {
+    pub fn new() -> AccessSet {
+        AccessSet {
+            addresses: HashSet::new(),
+            slots: HashSet::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    /// Parses an EIP-2930 access list, e.g.
+    /// `[{"address": "0x..", "storageKeys": ["0x.."]}]`
+    pub fn from_json(json: &str) -> Result<AccessSet, String> {
+        let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let entries = value.as_array().ok_or("expected a JSON array")?;
+        let mut result = AccessSet::new();
+        for entry in entries {
+            let address = entry.get("address")
+                .and_then(Value::as_str)
+                .and_then(|text| text.trim_start_matches("0x").parse::<Address>().ok())
+                .ok_or("invalid address")?;
+            result.insert_address(address);
+            if let Some(keys) = entry.get("storageKeys") {
+                let keys = keys.as_array().ok_or("invalid storageKeys")?;
+                for key in keys {
+                    let key = parse_u256(key).ok_or("invalid storage key")?;
+                    result.insert_slot(address, key);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn insert_address(&mut self, address: Address) {
+        self.addresses.insert(address);
+    }
+
+    pub fn insert_slot(&mut self, address: Address, key: U256) {
+        self.slots.insert((address, key));
+    }
+
+    /// Marks `address` as accessed, returns true if it was cold
+    pub fn access_address(&mut self, address: &Address) -> bool {
+        let cold = self.addresses.insert(*address);
+        if cold {
+            self.journal.push((*address, None));
+        }
+        cold
+    }
+
+    /// Marks the slot `key` of `address` as accessed, returns true if it was
+    /// cold
+    pub fn access_slot(&mut self, address: &Address, key: &U256) -> bool {
+        let cold = self.slots.insert((*address, *key));
+        if cold {
+            self.journal.push((*address, Some(*key)));
+        }
+        cold
+    }
+
+    /// Returns a checkpoint to revert to when a frame fails
+    pub fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// Makes the entries accessed since `checkpoint` cold again
+    pub fn revert(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            match self.journal.pop().unwrap() {
+                (address, Some(key)) => self.slots.remove(&(address, key)),
+                (address, None) => self.addresses.remove(&address)
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_access_is_cold() {
+        let mut access = AccessSet::new();
+        let address = Address::from_low_u64_be(1);
+        let key = U256::from_u64(7);
+        assert!(access.access_address(&address));
+        assert!(!access.access_address(&address));
+        assert!(access.access_slot(&address, &key));
+        assert!(!access.access_slot(&address, &key));
+        // slots are warmed independently of their account
+        assert!(access.access_slot(&Address::from_low_u64_be(2), &key));
+        assert!(access.access_address(&Address::from_low_u64_be(2)));
+    }
+
+    #[test]
+    fn revert_makes_entries_cold_again() {
+        let mut access = AccessSet::new();
+        let listed = Address::from_low_u64_be(1);
+        let address = Address::from_low_u64_be(2);
+        let key = U256::from_u64(7);
+        access.insert_address(listed);
+        access.insert_slot(listed, key);
+        let checkpoint = access.checkpoint();
+        assert!(!access.access_address(&listed));
+        assert!(access.access_address(&address));
+        assert!(access.access_slot(&address, &key));
+        access.revert(checkpoint);
+        // the access list is kept, the entries of the reverted frame are not
+        assert!(!access.access_address(&listed));
+        assert!(!access.access_slot(&listed, &key));
+        assert!(access.access_address(&address));
+        assert!(access.access_slot(&address, &key));
+    }
+}
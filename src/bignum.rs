@@ -0,0 +1,80 @@
+// Copyright 2019 The Psyche Authors
+// This file is part of Psyche.
+//
+// Psyche is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Psyche is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Psyche. If not, see <http://www.gnu.org/licenses/>.
+
+
+use crate::{divrem_limbs, mul_limbs, num_limbs};
+
+// Arbitrary precision unsigned integers are little endian `u64` limbs, as
+// in the fixed size `U256` helpers
+
+/// Parses a big endian byte string
+pub fn from_be_bytes(bytes: &[u8]) -> Vec<u64> {
+    let mut limbs = vec![0u64; bytes.len().div_ceil(8)];
+    for (i, b) in bytes.iter().rev().enumerate() {
+        limbs[i / 8] |= (*b as u64) << (8 * (i % 8));
+    }
+    limbs
+}
+
+/// Serializes to a big endian byte string of `len` bytes, the value must fit
+pub fn to_be_bytes(limbs: &[u64], len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    for i in 0..std::cmp::min(len, limbs.len() * 8) {
+        bytes[len - 1 - i] = (limbs[i / 8] >> (8 * (i % 8))) as u8;
+    }
+    bytes
+}
+
+/// Returns the product with `a.len() + b.len()` limbs
+pub fn mul(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut c = vec![0u64; a.len() + b.len()];
+    mul_limbs(a, b, &mut c);
+    c
+}
+
+/// Returns `a mod b` with as many limbs as `b`, `b` must not be zero
+pub fn rem(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut r = vec![0u64; b.len()];
+    divrem_limbs(a, b, &mut [], &mut r);
+    r
+}
+
+/// Returns `base ** exponent mod modulus`, the exponent being big endian
+/// bytes followed by `zero_bytes` zero bytes, which are not stored. The
+/// result is zero when the modulus is zero.
+pub fn mod_pow(base: &[u64], exponent: &[u8], zero_bytes: u64, modulus: &[u64]) -> Vec<u64> {
+    if num_limbs(modulus) == 0 {
+        return vec![0u64; modulus.len()];
+    }
+    let base = rem(base, modulus);
+    let mut result = rem(&[1], modulus);
+    for byte in exponent {
+        for i in (0..8).rev() {
+            result = rem(&mul(&result, &result), modulus);
+            if (byte >> i) & 1 != 0 {
+                result = rem(&mul(&result, &base), modulus);
+            }
+        }
+    }
+    // each zero byte squares the result 8 times, until it is zero or one
+    for _ in 0..zero_bytes.saturating_mul(8) {
+        if num_limbs(&result) == 0 || (num_limbs(&result) == 1 && result[0] == 1) {
+            break;
+        }
+        result = rem(&mul(&result, &result), modulus);
+    }
+    result
+}
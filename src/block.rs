@@ -0,0 +1,163 @@
+// Copyright 2019 The Psyche Authors
+// This file is part of Psyche.
+//
+// Psyche is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Psyche is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Psyche. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::collections::HashMap;
+use ethereum_types::Address;
+use serde_json::Value;
+use crate::keccak::keccak256;
+use crate::U256;
+
+/// Block header fields visible to executing code
+#[derive(Clone)]
+pub struct BlockEnv {
+    pub coinbase: Address,
+    pub timestamp: U256,
+    pub number: U256,
+    pub difficulty: U256,
+    /// Randomness beacon output, replaces `difficulty` after the Merge
+    pub prev_randao: U256,
+    pub gas_limit: U256,
+    pub chain_id: U256,
+    pub base_fee: U256,
+}
+
+impl BlockEnv {
+    pub fn default() -> BlockEnv {
+        BlockEnv {
+            coinbase: Address::zero(),
+            timestamp: U256::default(),
+            number: U256::default(),
+            difficulty: U256::default(),
+            prev_randao: U256::default(),
+            gas_limit: U256::default(),
+            chain_id: U256::default(),
+            base_fee: U256::default(),
+        }
+    }
+
+    /// Parses a block environment in the `env` format of the Ethereum state
+    /// tests (`currentCoinbase`, `currentNumber`, ...), missing fields are zero
+    pub fn from_json(json: &str) -> Result<BlockEnv, String> {
+        let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let object = value.as_object().ok_or("expected a JSON object")?;
+        let field = |name: &str| -> Result<U256, String> {
+            match object.get(name) {
+                Some(value) => parse_u256(value).ok_or(format!("invalid {}", name)),
+                None => Ok(U256::default())
+            }
+        };
+        let coinbase = match object.get("currentCoinbase") {
+            Some(value) => {
+                let text = value.as_str().ok_or("invalid currentCoinbase")?;
+                let text = text.trim_start_matches("0x");
+                text.parse::<Address>().map_err(|_| "invalid currentCoinbase")?
+            },
+            None => Address::zero()
+        };
+        Ok(BlockEnv {
+            coinbase,
+            timestamp: field("currentTimestamp")?,
+            number: field("currentNumber")?,
+            difficulty: field("currentDifficulty")?,
+            prev_randao: field("currentRandom")?,
+            gas_limit: field("currentGasLimit")?,
+            chain_id: field("chainId")?,
+            base_fee: field("currentBaseFee")?,
+        })
+    }
+}
+
+/// Hashes of past blocks, implemented by the embedding code
+pub trait BlockHashes {
+    /// Returns the hash of block `number`, only queried for the 256 most
+    /// recent blocks
+    fn block_hash(&self, number: u64) -> U256;
+}
+
+/// Block hashes given by a map, unknown blocks hash to zero
+pub struct MemoryBlockHashes {
+    hashes: HashMap<u64, U256>,
+}
+
+impl MemoryBlockHashes {
+    pub fn new() -> MemoryBlockHashes {
+        MemoryBlockHashes { hashes: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, number: u64, hash: U256) {
+        self.hashes.insert(number, hash);
+    }
+
+    /// Parses a JSON object mapping block numbers to hashes, e.g.
+    /// `{"41": "0x1f..", "0x2a": "0x3c.."}`
+    pub fn from_json(json: &str) -> Result<MemoryBlockHashes, String> {
+        let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let object = value.as_object().ok_or("expected a JSON object")?;
+        let mut result = MemoryBlockHashes::new();
+        for (key, value) in object {
+            let number = parse_u256(&Value::String(key.clone()))
+                .filter(|number| number.le_u64())
+                .ok_or(format!("invalid block number {}", key))?;
+            let hash = parse_u256(value).ok_or(format!("invalid hash of block {}", key))?;
+            result.insert(number.low_u64(), hash);
+        }
+        Ok(result)
+    }
+}
+
+impl BlockHashes for MemoryBlockHashes {
+    fn block_hash(&self, number: u64) -> U256 {
+        match self.hashes.get(&number) {
+            Some(hash) => *hash,
+            None => U256::default()
+        }
+    }
+}
+
+/// Deterministic stand-in hashes, block `n` hashes to the Keccak-256 of the
+/// decimal string of `n`
+pub struct SyntheticBlockHashes;
+
+impl BlockHashes for SyntheticBlockHashes {
+    fn block_hash(&self, number: u64) -> U256 {
+        U256::from_be_bytes(&keccak256(number.to_string().as_bytes()))
+    }
+}
+
+/// Parses a JSON number, a `0x` prefixed hex string or a decimal string
+pub fn parse_u256(value: &Value) -> Option<U256> {
+    if let Some(number) = value.as_u64() {
+        return Some(U256::from_u64(number));
+    }
+    let text = value.as_str()?;
+    let temp = if let Some(hex) = text.strip_prefix("0x") {
+        if hex.is_empty() {
+            return None;
+        }
+        let digits = hex.trim_start_matches('0');
+        if digits.is_empty() {
+            return Some(U256::default());
+        }
+        if digits.len() > 64 {
+            return None;
+        }
+        digits.parse::<ethereum_types::U256>().ok()?
+    } else {
+        ethereum_types::U256::from_dec_str(text).ok()?
+    };
+    Some(U256::from_slice(&temp.0))
+}
@@ -16,6 +16,8 @@
 
 use num_traits::FromPrimitive;
 use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+use crate::schedule::Fork;
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone, FromPrimitive)]
 #[repr(u8)]
@@ -24,8 +26,18 @@ pub enum Opcode {
     ADD,
     MUL,
     SUB,
+    DIV,
+    SDIV,
+    MOD,
+    SMOD,
+    ADDMOD,
+    MULMOD,
+    EXP,
     SIGNEXTEND,
+    LT,
     GT,
+    SLT,
+    SGT,
     EQ,
     ISZERO,
     AND,
@@ -34,17 +46,50 @@ pub enum Opcode {
     NOT,
     BYTE,
     SHL,
+    SHR,
+    SAR,
+    SHA3,
+    ADDRESS,
+    BALANCE,
+    ORIGIN,
+    CALLER,
+    CALLVALUE,
+    CALLDATALOAD,
+    CALLDATASIZE,
+    CALLDATACOPY,
     CODESIZE,
+    CODECOPY,
+    GASPRICE,
+    EXTCODESIZE,
+    EXTCODECOPY,
+    RETURNDATASIZE,
+    RETURNDATACOPY,
+    EXTCODEHASH,
+    BLOCKHASH,
+    COINBASE,
+    TIMESTAMP,
+    NUMBER,
+    DIFFICULTY,
+    GASLIMIT,
+    CHAINID,
+    SELFBALANCE,
+    BASEFEE,
     POP,
     MLOAD,
     MSTORE,
     MSTORE8,
+    SLOAD,
+    SSTORE,
     JUMP,
     JUMPI,
     PC,
     MSIZE,
     GAS,
     JUMPDEST,
+    TLOAD,
+    TSTORE,
+    MCOPY,
+    PUSH0,
     PUSH1,
     PUSH2,
     PUSH3,
@@ -109,8 +154,21 @@ pub enum Opcode {
     SWAP14,
     SWAP15,
     SWAP16,
+    LOG0,
+    LOG1,
+    LOG2,
+    LOG3,
+    LOG4,
+    CREATE,
+    CALL,
+    CALLCODE,
     RETURN,
-    INVALID
+    DELEGATECALL,
+    CREATE2,
+    STATICCALL,
+    REVERT,
+    INVALID,
+    SELFDESTRUCT
 }
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone, FromPrimitive, TryFromPrimitive)]
@@ -120,8 +178,18 @@ pub enum EvmOpcode {
     ADD = 0x01,
     MUL = 0x02,
     SUB = 0x03,
+    DIV = 0x04,
+    SDIV = 0x05,
+    MOD = 0x06,
+    SMOD = 0x07,
+    ADDMOD = 0x08,
+    MULMOD = 0x09,
+    EXP = 0x0a,
     SIGNEXTEND = 0x0b,
+    LT = 0x10,
     GT = 0x11,
+    SLT = 0x12,
+    SGT = 0x13,
     EQ = 0x14,
     ISZERO = 0x15,
     AND = 0x16,
@@ -130,17 +198,50 @@ pub enum EvmOpcode {
     NOT = 0x19,
     BYTE = 0x1a,
     SHL = 0x1b,
+    SHR = 0x1c,
+    SAR = 0x1d,
+    SHA3 = 0x20,
+    ADDRESS = 0x30,
+    BALANCE = 0x31,
+    ORIGIN = 0x32,
+    CALLER = 0x33,
+    CALLVALUE = 0x34,
+    CALLDATALOAD = 0x35,
+    CALLDATASIZE = 0x36,
+    CALLDATACOPY = 0x37,
     CODESIZE = 0x38,
+    CODECOPY = 0x39,
+    GASPRICE = 0x3a,
+    EXTCODESIZE = 0x3b,
+    EXTCODECOPY = 0x3c,
+    RETURNDATASIZE = 0x3d,
+    RETURNDATACOPY = 0x3e,
+    EXTCODEHASH = 0x3f,
+    BLOCKHASH = 0x40,
+    COINBASE = 0x41,
+    TIMESTAMP = 0x42,
+    NUMBER = 0x43,
+    DIFFICULTY = 0x44,
+    GASLIMIT = 0x45,
+    CHAINID = 0x46,
+    SELFBALANCE = 0x47,
+    BASEFEE = 0x48,
     POP = 0x50,
     MLOAD = 0x51,
     MSTORE = 0x52,
     MSTORE8 = 0x53,
+    SLOAD = 0x54,
+    SSTORE = 0x55,
     JUMP = 0x56,
     JUMPI = 0x57,
     PC = 0x58,
     MSIZE = 0x59,
     GAS = 0x5a,
     JUMPDEST = 0x5b,
+    TLOAD = 0x5c,
+    TSTORE = 0x5d,
+    MCOPY = 0x5e,
+    PUSH0 = 0x5f,
     PUSH1 = 0x60,
     PUSH2 = 0x61,
     PUSH3 = 0x62,
@@ -205,8 +306,21 @@ pub enum EvmOpcode {
     SWAP14 = 0x9d,
     SWAP15 = 0x9e,
     SWAP16 = 0x9f,
+    LOG0 = 0xa0,
+    LOG1 = 0xa1,
+    LOG2 = 0xa2,
+    LOG3 = 0xa3,
+    LOG4 = 0xa4,
+    CREATE = 0xf0,
+    CALL = 0xf1,
+    CALLCODE = 0xf2,
     RETURN = 0xf3,
-    INVALID = 0xfe
+    DELEGATECALL = 0xf4,
+    CREATE2 = 0xf5,
+    STATICCALL = 0xfa,
+    REVERT = 0xfd,
+    INVALID = 0xfe,
+    SELFDESTRUCT = 0xff
 }
 
 use std::fmt;
@@ -246,6 +360,12 @@ impl Opcode {
     pub fn swap_index(&self) -> usize {
         ((*self as u8) - (Opcode::SWAP1 as u8)) as usize
     }
+
+    /// Returns the number of topics of the `LOGN` opcode
+    /// LOG1 -> 1
+    pub fn log_index(&self) -> usize {
+        ((*self as u8) - (Opcode::LOG0 as u8)) as usize
+    }
 }
 
 impl EvmOpcode {
@@ -260,7 +380,11 @@ impl EvmOpcode {
     pub fn is_terminator(&self) -> bool {
         match *self {
             EvmOpcode::STOP | EvmOpcode::JUMP |
-            EvmOpcode::JUMPI | EvmOpcode::INVALID | EvmOpcode::GAS => true,
+            EvmOpcode::JUMPI | EvmOpcode::INVALID | EvmOpcode::GAS |
+            EvmOpcode::SSTORE | EvmOpcode::RETURN | EvmOpcode::REVERT |
+            EvmOpcode::CALL | EvmOpcode::CALLCODE | EvmOpcode::DELEGATECALL |
+            EvmOpcode::STATICCALL | EvmOpcode::CREATE | EvmOpcode::CREATE2 |
+            EvmOpcode::SELFDESTRUCT => true,
             _ => false
         }
     }
@@ -271,9 +395,35 @@ impl EvmOpcode {
         ((*self as u8) - (EvmOpcode::PUSH1 as u8)) as usize
     }
 
+    /// Returns the fork that introduced the opcode
+    /// CHAINID -> Istanbul
+    pub fn fork(&self) -> Fork {
+        match *self {
+            EvmOpcode::SHL | EvmOpcode::SHR | EvmOpcode::SAR |
+            EvmOpcode::EXTCODEHASH | EvmOpcode::CREATE2 => Fork::Constantinople,
+            EvmOpcode::DELEGATECALL => Fork::Homestead,
+            EvmOpcode::RETURNDATASIZE | EvmOpcode::RETURNDATACOPY |
+            EvmOpcode::STATICCALL | EvmOpcode::REVERT => Fork::Byzantium,
+            EvmOpcode::CHAINID | EvmOpcode::SELFBALANCE => Fork::Istanbul,
+            EvmOpcode::BASEFEE => Fork::London,
+            EvmOpcode::PUSH0 => Fork::Shanghai,
+            EvmOpcode::TLOAD | EvmOpcode::TSTORE | EvmOpcode::MCOPY => Fork::Cancun,
+            _ => Fork::Frontier
+        }
+    }
+
+    /// Decodes a byte of bytecode, undefined opcodes and opcodes
+    /// not yet introduced in the given fork decode to `INVALID`
+    pub fn decode(code: u8, fork: Fork) -> EvmOpcode {
+        match EvmOpcode::try_from(code) {
+            Ok(opcode) if opcode.fork() <= fork => opcode,
+            _ => EvmOpcode::INVALID
+        }
+    }
+
     /// Convert to internal representation
     pub fn to_internal(&self) -> Opcode {
-        const MAPPING: [Opcode; 256] = [Opcode::STOP, Opcode::ADD, Opcode::MUL, Opcode::SUB, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::SIGNEXTEND, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::GT, Opcode::INVALID, Opcode::INVALID, Opcode::EQ, Opcode::ISZERO, Opcode::AND, Opcode::OR, Opcode::XOR, Opcode::NOT, Opcode::BYTE, Opcode::SHL, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::CODESIZE, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::POP, Opcode::MLOAD, Opcode::MSTORE, Opcode::MSTORE8, Opcode::INVALID, Opcode::INVALID, Opcode::JUMP, Opcode::JUMPI, Opcode::PC, Opcode::MSIZE, Opcode::GAS, Opcode::JUMPDEST, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::PUSH1, Opcode::PUSH2, Opcode::PUSH3, Opcode::PUSH4, Opcode::PUSH5, Opcode::PUSH6, Opcode::PUSH7, Opcode::PUSH8, Opcode::PUSH9, Opcode::PUSH10, Opcode::PUSH11, Opcode::PUSH12, Opcode::PUSH13, Opcode::PUSH14, Opcode::PUSH15, Opcode::PUSH16, Opcode::PUSH17, Opcode::PUSH18, Opcode::PUSH19, Opcode::PUSH20, Opcode::PUSH21, Opcode::PUSH22, Opcode::PUSH23, Opcode::PUSH24, Opcode::PUSH25, Opcode::PUSH26, Opcode::PUSH27, Opcode::PUSH28, Opcode::PUSH29, Opcode::PUSH30, Opcode::PUSH31, Opcode::PUSH32, Opcode::DUP1, Opcode::DUP2, Opcode::DUP3, Opcode::DUP4, Opcode::DUP5, Opcode::DUP6, Opcode::DUP7, Opcode::DUP8, Opcode::DUP9, Opcode::DUP10, Opcode::DUP11, Opcode::DUP12, Opcode::DUP13, Opcode::DUP14, Opcode::DUP15, Opcode::DUP16, Opcode::SWAP1, Opcode::SWAP2, Opcode::SWAP3, Opcode::SWAP4, Opcode::SWAP5, Opcode::SWAP6, Opcode::SWAP7, Opcode::SWAP8, Opcode::SWAP9, Opcode::SWAP10, Opcode::SWAP11, Opcode::SWAP12, Opcode::SWAP13, Opcode::SWAP14, Opcode::SWAP15, Opcode::SWAP16, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::RETURN, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID];
+        const MAPPING: [Opcode; 256] = [Opcode::STOP, Opcode::ADD, Opcode::MUL, Opcode::SUB, Opcode::DIV, Opcode::SDIV, Opcode::MOD, Opcode::SMOD, Opcode::ADDMOD, Opcode::MULMOD, Opcode::EXP, Opcode::SIGNEXTEND, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::LT, Opcode::GT, Opcode::SLT, Opcode::SGT, Opcode::EQ, Opcode::ISZERO, Opcode::AND, Opcode::OR, Opcode::XOR, Opcode::NOT, Opcode::BYTE, Opcode::SHL, Opcode::SHR, Opcode::SAR, Opcode::INVALID, Opcode::INVALID, Opcode::SHA3, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::ADDRESS, Opcode::BALANCE, Opcode::ORIGIN, Opcode::CALLER, Opcode::CALLVALUE, Opcode::CALLDATALOAD, Opcode::CALLDATASIZE, Opcode::CALLDATACOPY, Opcode::CODESIZE, Opcode::CODECOPY, Opcode::GASPRICE, Opcode::EXTCODESIZE, Opcode::EXTCODECOPY, Opcode::RETURNDATASIZE, Opcode::RETURNDATACOPY, Opcode::EXTCODEHASH, Opcode::BLOCKHASH, Opcode::COINBASE, Opcode::TIMESTAMP, Opcode::NUMBER, Opcode::DIFFICULTY, Opcode::GASLIMIT, Opcode::CHAINID, Opcode::SELFBALANCE, Opcode::BASEFEE, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::POP, Opcode::MLOAD, Opcode::MSTORE, Opcode::MSTORE8, Opcode::SLOAD, Opcode::SSTORE, Opcode::JUMP, Opcode::JUMPI, Opcode::PC, Opcode::MSIZE, Opcode::GAS, Opcode::JUMPDEST, Opcode::TLOAD, Opcode::TSTORE, Opcode::MCOPY, Opcode::PUSH0, Opcode::PUSH1, Opcode::PUSH2, Opcode::PUSH3, Opcode::PUSH4, Opcode::PUSH5, Opcode::PUSH6, Opcode::PUSH7, Opcode::PUSH8, Opcode::PUSH9, Opcode::PUSH10, Opcode::PUSH11, Opcode::PUSH12, Opcode::PUSH13, Opcode::PUSH14, Opcode::PUSH15, Opcode::PUSH16, Opcode::PUSH17, Opcode::PUSH18, Opcode::PUSH19, Opcode::PUSH20, Opcode::PUSH21, Opcode::PUSH22, Opcode::PUSH23, Opcode::PUSH24, Opcode::PUSH25, Opcode::PUSH26, Opcode::PUSH27, Opcode::PUSH28, Opcode::PUSH29, Opcode::PUSH30, Opcode::PUSH31, Opcode::PUSH32, Opcode::DUP1, Opcode::DUP2, Opcode::DUP3, Opcode::DUP4, Opcode::DUP5, Opcode::DUP6, Opcode::DUP7, Opcode::DUP8, Opcode::DUP9, Opcode::DUP10, Opcode::DUP11, Opcode::DUP12, Opcode::DUP13, Opcode::DUP14, Opcode::DUP15, Opcode::DUP16, Opcode::SWAP1, Opcode::SWAP2, Opcode::SWAP3, Opcode::SWAP4, Opcode::SWAP5, Opcode::SWAP6, Opcode::SWAP7, Opcode::SWAP8, Opcode::SWAP9, Opcode::SWAP10, Opcode::SWAP11, Opcode::SWAP12, Opcode::SWAP13, Opcode::SWAP14, Opcode::SWAP15, Opcode::SWAP16, Opcode::LOG0, Opcode::LOG1, Opcode::LOG2, Opcode::LOG3, Opcode::LOG4, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::CREATE, Opcode::CALL, Opcode::CALLCODE, Opcode::RETURN, Opcode::DELEGATECALL, Opcode::CREATE2, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::INVALID, Opcode::STATICCALL, Opcode::INVALID, Opcode::INVALID, Opcode::REVERT, Opcode::INVALID, Opcode::SELFDESTRUCT];
         MAPPING[*self as usize]
     }
 }
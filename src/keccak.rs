@@ -0,0 +1,124 @@
+// Copyright 2019 The Psyche Authors
+// This file is part of Psyche.
+//
+// Psyche is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Psyche is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Psyche. If not, see <http://www.gnu.org/licenses/>.
+
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+    0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+    0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+
+const RHO: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44
+];
+
+const PI: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1
+];
+
+/// Number of bytes absorbed per permutation for a 256-bit output
+const RATE: usize = 136;
+
+fn keccak_f(a: &mut [u64; 25]) {
+    for round_constant in ROUND_CONSTANTS.iter() {
+        // theta
+        let mut c: [u64; 5] = [0; 5];
+        for x in 0..5 {
+            c[x] = a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                a[x + 5 * y] ^= d;
+            }
+        }
+        // rho and pi
+        let mut last = a[1];
+        for i in 0..24 {
+            let j = PI[i];
+            let temp = a[j];
+            a[j] = last.rotate_left(RHO[i]);
+            last = temp;
+        }
+        // chi
+        for y in 0..5 {
+            let row = [a[5 * y], a[5 * y + 1], a[5 * y + 2], a[5 * y + 3], a[5 * y + 4]];
+            for x in 0..5 {
+                a[5 * y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+        // iota
+        a[0] ^= round_constant;
+    }
+}
+
+fn absorb(state: &mut [u64; 25], block: &[u8]) {
+    for (i, lane) in block.chunks(8).enumerate() {
+        let mut bytes: [u8; 8] = [0; 8];
+        bytes.copy_from_slice(lane);
+        state[i] ^= u64::from_le_bytes(bytes);
+    }
+    keccak_f(state);
+}
+
+/// Returns the Keccak-256 digest of `input` (original padding, not SHA3-256)
+pub fn keccak256(input: &[u8]) -> [u8; 32] {
+    let mut state: [u64; 25] = [0; 25];
+    let mut chunks = input.chunks_exact(RATE);
+    for block in &mut chunks {
+        absorb(&mut state, block);
+    }
+    let remainder = chunks.remainder();
+    let mut block: [u8; RATE] = [0; RATE];
+    block[..remainder.len()].copy_from_slice(remainder);
+    block[remainder.len()] ^= 0x01;
+    block[RATE - 1] ^= 0x80;
+    absorb(&mut state, &block);
+    //
+    let mut result: [u8; 32] = [0; 32];
+    for i in 0..4 {
+        result[8 * i..8 * (i + 1)].copy_from_slice(&state[i].to_le_bytes());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(hex(&keccak256(b"")), "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+        assert_eq!(hex(&keccak256(b"abc")), "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45");
+    }
+
+    #[test]
+    fn rate_boundaries() {
+        // one byte short of the rate puts both padding bits in the same byte
+        assert_eq!(hex(&keccak256(&[b'a'; RATE - 1])), "34367dc248bbd832f4e3e69dfaac2f92638bd0bbd18f2912ba4ef454919cf446");
+        // a full block is followed by a block of padding only
+        assert_eq!(hex(&keccak256(&[b'a'; RATE])), "a6c4d403279fe3e0af03729caada8374b5ca54d8065329a3ebcaeb4b60aa386e");
+        assert_eq!(hex(&keccak256(&[b'a'; RATE + 1])), "d869f639c7046b4929fc92a4d988a8b22c55fbadb802c0c66ebcd484f1915f39");
+        assert_eq!(hex(&keccak256(&[b'a'; 2 * RATE])), "cf7fcd4f705ee749930d19ca84561a9bf62516bd90a471545fa2f49fdc7e63c8");
+    }
+}
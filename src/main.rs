@@ -23,13 +23,22 @@ extern crate memmap;
 extern crate num_derive;
 extern crate num_traits;
 
+mod access;
+mod bignum;
+mod block;
 mod instructions;
+mod keccak;
+mod precompiles;
 mod schedule;
+mod state;
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-use clap::{Arg, App, SubCommand};
+use access::AccessSet;
+use block::{BlockEnv, BlockHashes, MemoryBlockHashes, SyntheticBlockHashes};
+use clap::{Arg, App, ArgMatches, SubCommand};
+use ethereum_types::Address;
 use memmap::Mmap;
 use num_traits::FromPrimitive;
 use std::convert::TryFrom;
@@ -38,11 +47,13 @@ use std::fmt;
 use std::{fmt::Write, num::ParseIntError};
 use instructions::{EvmOpcode, EvmInstruction, Opcode};
 use instructions::Opcode::*;
-use schedule::{Fork, Fee, Schedule};
+use keccak::keccak256;
+use schedule::{Fork, Fee, Schedule, SstoreMetering};
 use schedule::Fee::*;
+use state::{parse_alloc, Account, AccountState, MemoryState, MemoryStorage, Storage, TransientStorage};
 
 #[repr(align(32))]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 struct U256(pub [u64; 4]);
 
 impl U256 {
@@ -58,19 +69,76 @@ impl U256 {
         return U256 { 0: [value, 0, 0, 0] };
     }
 
+    pub fn from_u128(value: u128) -> U256 {
+        U256([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    /// Converts from big-endian bytes, `bytes` must not exceed 32 bytes
+    pub fn from_be_bytes(bytes: &[u8]) -> U256 {
+        let mut result = U256::default();
+        for (i, byte) in bytes.iter().rev().enumerate() {
+            result.0[i / 8] |= (*byte as u64) << (8 * (i % 8));
+        }
+        result
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut result: [u8; 32] = [0; 32];
+        for i in 0..4 {
+            result[8*i..8*(i+1)].copy_from_slice(&self.0[3-i].to_be_bytes());
+        }
+        result
+    }
+
+    pub fn from_address(address: &Address) -> U256 {
+        U256::from_be_bytes(address.as_bytes())
+    }
+
+    /// Returns the address held by the lowest 160 bits
+    pub fn to_address(&self) -> Address {
+        Address::from_slice(&self.to_be_bytes()[12..])
+    }
+
     pub fn low_u64(&self) -> u64 {
         return self.0[0];
     }
 
+    /// Returns the value as `u64`, or `u64::max_value()` if it does not fit
+    pub fn saturating_u64(&self) -> u64 {
+        if self.le_u64() {
+            self.low_u64()
+        } else {
+            u64::MAX
+        }
+    }
+
     pub fn low_u128(&self) -> u128 {
         let lo = self.0[0];
         let hi = self.0[1];
-        lo as u128 | (hi as u128 >> 64)
+        lo as u128 | ((hi as u128) << 64)
     }
 
     pub fn le_u64(&self) -> bool {
         (self.0[1] == 0) & (self.0[2] == 0) & (self.0[3] == 0)
     }
+
+    pub fn le_u128(&self) -> bool {
+        (self.0[2] == 0) & (self.0[3] == 0)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        (self.0[0] == 0) & (self.0[1] == 0) & (self.0[2] == 0) & (self.0[3] == 0)
+    }
+
+    /// Returns the number of bits needed to represent the value
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return (64 * i as u32) + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
 }
 
 trait __m256iExt {
@@ -135,6 +203,18 @@ pub enum VmError {
     OutOfGas,
     InvalidJumpDest,
     InvalidInstruction,
+    /// State modification attempted in a static call
+    WriteProtection,
+    /// RETURNDATACOPY read past the end of the return data
+    ReturnDataOutOfBounds,
+    /// CREATE target already has code or a nonce
+    AddressCollision,
+    /// Deployed code exceeds the EIP-170 limit
+    CodeSizeExceeded,
+    /// Deployed code starts with the reserved 0xEF byte (EIP-3541)
+    InvalidCode,
+    /// A precompiled contract rejected its input
+    PrecompileFailure,
 }
 
 #[allow(unreachable_code)]
@@ -615,6 +695,154 @@ unsafe fn shl_u256(count: U256, value: U256) -> U256 {
     unimplemented!()
 }
 
+#[allow(unreachable_code)]
+unsafe fn shr_u256(count: U256, value: U256) -> U256 {
+    #[cfg(target_feature = "avx2")]
+    {
+        let one = _mm256_set_epi64x(0, 0, 0, 1);
+        let sixty_four = _mm_set_epi64x(0, 64);
+        let max_u8 = _mm256_sub_epi8(_mm256_setzero_si256(), one);
+        let hi_u64 = _mm256_set_epi64x(-1, 0, 0, 0);
+        //
+        let count = std::mem::transmute::<U256, __m256i>(count);
+        let value = std::mem::transmute::<U256, __m256i>(value);
+        let hi248 = _mm256_andnot_si256(max_u8, count);
+        let hiisz = broadcast_avx2(is_zero_u256(hi248.as_u256()));
+        let mut temp = value;
+        let mut current = _mm256_castsi256_si128(count);
+        let mut i = 0;
+        while i < 4 {
+            let srcount = _mm_min_epu8(sixty_four, current);
+            let slcount = _mm_subs_epu8(sixty_four, srcount);
+            let srtemp = _mm256_srl_epi64(temp, srcount);
+            let sltemp = _mm256_sll_epi64(temp, slcount);
+            let carry = _mm256_permute4x64_epi64(sltemp, _MM_SHUFFLE(0, 3, 2, 1));
+            temp = _mm256_or_si256(srtemp, _mm256_andnot_si256(hi_u64, carry));
+            current = _mm_subs_epu8(current, srcount);
+            i += 1;
+        }
+        let result = _mm256_and_si256(temp, hiisz);
+        return std::mem::transmute::<__m256i, U256>(result);
+    }
+    #[cfg(target_feature = "ssse3")]
+    {
+        let zero = _mm_setzero_si128();
+        let one = _mm_set_epi64x(0, 1);
+        let sixty_four = _mm_set_epi64x(0, 64);
+        let max_u8 = _mm_sub_epi8(zero, one);
+        //
+        let count = std::mem::transmute::<U256, (__m128i, __m128i)>(count);
+        let value = std::mem::transmute::<U256, (__m128i, __m128i)>(value);
+        let hi248 = (_mm_andnot_si128(max_u8, count.0), count.1);
+        let hi248 = std::mem::transmute::<(__m128i, __m128i), U256>(hi248);
+        let hiisz = broadcast_sse2(is_zero_u256(hi248));
+        let mut temp = value;
+        let mut current = count.0;
+        let mut i = 0;
+        while i < 4 {
+            let srcount = _mm_min_epu8(sixty_four, current);
+            let slcount = _mm_subs_epu8(sixty_four, srcount);
+            let srtemplo = _mm_srl_epi64(temp.0, srcount);
+            let srtemphi = _mm_srl_epi64(temp.1, srcount);
+            let sltemplo = _mm_sll_epi64(temp.0, slcount);
+            let sltemphi = _mm_sll_epi64(temp.1, slcount);
+            let carrylo = _mm_unpacklo_epi64(_mm_bsrli_si128(sltemplo, 8), sltemphi);
+            let carryhi = _mm_bsrli_si128(sltemphi, 8);
+            let templo = _mm_or_si128(srtemplo, carrylo);
+            let temphi = _mm_or_si128(srtemphi, carryhi);
+            temp = (templo, temphi);
+            current = _mm_subs_epu8(current, srcount);
+            i += 1;
+        }
+        let result = (_mm_and_si128(hiisz, temp.0), _mm_and_si128(hiisz, temp.1));
+        return std::mem::transmute::<(__m128i, __m128i), U256>(result);
+    }
+    unimplemented!()
+}
+
+#[allow(unreachable_code)]
+unsafe fn sar_u256(count: U256, value: U256) -> U256 {
+    // an arithmetic shift is a logical shift of the value with its sign bit
+    // flipped, flipped back
+    #[cfg(target_feature = "avx2")]
+    {
+        let signmask = broadcast_avx2(is_neg_u256(value));
+        //
+        let value = std::mem::transmute::<U256, __m256i>(value);
+        let temp = _mm256_xor_si256(value, signmask);
+        let temp = std::mem::transmute::<U256, __m256i>(shr_u256(count, temp.as_u256()));
+        let result = _mm256_xor_si256(temp, signmask);
+        return std::mem::transmute::<__m256i, U256>(result);
+    }
+    #[cfg(target_feature = "ssse3")]
+    {
+        let signmask = broadcast_sse2(is_neg_u256(value));
+        //
+        let value = std::mem::transmute::<U256, (__m128i, __m128i)>(value);
+        let temp = (_mm_xor_si128(value.0, signmask), _mm_xor_si128(value.1, signmask));
+        let temp = std::mem::transmute::<(__m128i, __m128i), U256>(temp);
+        let temp = std::mem::transmute::<U256, (__m128i, __m128i)>(shr_u256(count, temp));
+        let result = (_mm_xor_si128(temp.0, signmask), _mm_xor_si128(temp.1, signmask));
+        return std::mem::transmute::<(__m128i, __m128i), U256>(result);
+    }
+    unimplemented!()
+}
+
+/// Returns the lane masks of `a > b` and `a < b`, lanes being ordered from
+/// least to most significant so that comparing both masks gives the result
+#[allow(unreachable_code)]
+unsafe fn cmp_masks_u256(a: U256, b: U256, signed: bool) -> (i32, i32) {
+    #[cfg(target_feature = "avx2")]
+    {
+        let min_i64 = i64::MIN;
+        let bias = _mm256_set_epi64x(if signed { 0 } else { min_i64 }, min_i64, min_i64, min_i64);
+        //
+        let a = std::mem::transmute::<U256, __m256i>(a);
+        let b = std::mem::transmute::<U256, __m256i>(b);
+        let biaseda = _mm256_xor_si256(a, bias);
+        let biasedb = _mm256_xor_si256(b, bias);
+        let gt = _mm256_cmpgt_epi64(biaseda, biasedb);
+        let lt = _mm256_cmpgt_epi64(biasedb, biaseda);
+        let gtmask = _mm256_movemask_pd(_mm256_castsi256_pd(gt));
+        let ltmask = _mm256_movemask_pd(_mm256_castsi256_pd(lt));
+        return (gtmask, ltmask);
+    }
+    #[cfg(target_feature = "ssse3")]
+    {
+        let min_i32 = i32::MIN;
+        let biaslo = _mm_set1_epi32(min_i32);
+        let biashi = _mm_set_epi32(if signed { 0 } else { min_i32 }, min_i32, min_i32, min_i32);
+        //
+        let a = std::mem::transmute::<U256, (__m128i, __m128i)>(a);
+        let b = std::mem::transmute::<U256, (__m128i, __m128i)>(b);
+        let biasedalo = _mm_xor_si128(a.0, biaslo);
+        let biasedahi = _mm_xor_si128(a.1, biashi);
+        let biasedblo = _mm_xor_si128(b.0, biaslo);
+        let biasedbhi = _mm_xor_si128(b.1, biashi);
+        let gtlo = _mm_movemask_ps(_mm_castsi128_ps(_mm_cmpgt_epi32(biasedalo, biasedblo)));
+        let gthi = _mm_movemask_ps(_mm_castsi128_ps(_mm_cmpgt_epi32(biasedahi, biasedbhi)));
+        let ltlo = _mm_movemask_ps(_mm_castsi128_ps(_mm_cmpgt_epi32(biasedblo, biasedalo)));
+        let lthi = _mm_movemask_ps(_mm_castsi128_ps(_mm_cmpgt_epi32(biasedbhi, biasedahi)));
+        return (gtlo | (gthi << 4), ltlo | (lthi << 4));
+    }
+    unimplemented!()
+}
+
+unsafe fn lt_u256(a: U256, b: U256) -> bool {
+    let (gtmask, ltmask) = cmp_masks_u256(a, b, false);
+    ltmask > gtmask
+}
+
+unsafe fn slt_u256(a: U256, b: U256) -> bool {
+    let (gtmask, ltmask) = cmp_masks_u256(a, b, true);
+    ltmask > gtmask
+}
+
+unsafe fn sgt_u256(a: U256, b: U256) -> bool {
+    let (gtmask, ltmask) = cmp_masks_u256(a, b, true);
+    gtmask > ltmask
+}
+
 fn overflowing_add_u256(a: U256, b: U256) -> (U256, bool) {
     let t0 = (a.0[0] as u128) + (b.0[0] as u128);
     let c0 = t0 >> 64;
@@ -649,73 +877,37 @@ fn mul_u64(a: u64, b: u64) -> u128 {
     (a as u128) * (b as u128)
 }
 
-fn mul_diag(num_limbs: usize, i: usize, a: &[u64], b: u64, r: &mut [u64], c: &mut [u64]) {
-    let mut carry: u64 = 0;
-    for j in 0..num_limbs {
-        let temp = mul_u64(a[j], b);
-        if j == 0 {
-            c[i] = temp as u64;
-            carry = (temp >> 64) as u64;
-        }
-        else {
-            let temp2 = temp + (carry as u128);
-            if j == (num_limbs - 1) {
-                r[j-1] = temp2 as u64;
-                r[j-0] = (temp2 >> 64) as u64;
-            }
-            else {
-                r[j-1] = temp2 as u64;
-                carry = (temp2 >> 64) as u64;
-            }
-        }
+/// Schoolbook multiplication of limb slices of any length, `c` receives the
+/// `a.len() + b.len()` limbs of the product
+fn mul_limbs(a: &[u64], b: &[u64], c: &mut [u64]) {
+    let n = a.len();
+    for x in c[..n].iter_mut() {
+        *x = 0;
     }
-}
-
-fn mul_diagc(num_limbs: usize, i: usize, a: &[u64], b: u64, r: &mut [u64], rp: &mut [u64], c: &mut [u64]) {
-    let mut carry: u64 = 0;
-    for j in 0..num_limbs {
-        let temp = mul_u64(a[j], b) + (r[j] as u128);
-        if j == 0 {
-            c[i] = temp as u64;
+    for i in 0..b.len() {
+        let mut carry: u64 = 0;
+        for j in 0..n {
+            let temp = mul_u64(a[j], b[i]) + (c[i+j] as u128) + (carry as u128);
+            c[i+j] = temp as u64;
             carry = (temp >> 64) as u64;
         }
-        else {
-            let temp2 = temp + (carry as u128);
-            if j == (num_limbs - 1) {
-                rp[j-1] = temp2 as u64;
-                rp[j-0] = (temp2 >> 64) as u64;
-            }
-            else {
-                rp[j-1] = temp2 as u64;
-                carry = (temp2 >> 64) as u64;
-            }
-        }
-    }
-}
-
-fn mul_limbs(num_limbs: usize, a: &[u64], b: &[u64], c: &mut [u64]) {
-    assert!(num_limbs <= 4);
-    let mut r: [u64; 8] = unsafe { std::mem::uninitialized() };
-    let mut rp: [u64; 8] = unsafe { std::mem::uninitialized() };
-    //
-    mul_diag(num_limbs, 0, a, b[0], &mut r, c);
-    for i in 1..num_limbs {
-        mul_diagc(num_limbs, i, a, b[i], &mut r, &mut rp, c);
-        for j in 0..num_limbs {
-            r[j] = rp[j];
-        }
-    }
-    for i in 0..num_limbs {
-        c[num_limbs+i] = rp[i];
+        c[i+n] = carry;
     }
 }
 
 fn mul_u256(a: U256, b: U256) -> U256 {
     let mut c: [u64; 8] = unsafe { std::mem::uninitialized() };
-    mul_limbs(4, &a.0, &b.0, &mut c);
+    mul_limbs(&a.0, &b.0, &mut c);
     U256([c[0], c[1], c[2], c[3]])
 }
 
+/// Returns the full 512-bit product
+fn full_mul_u256(a: U256, b: U256) -> [u64; 8] {
+    let mut c: [u64; 8] = [0; 8];
+    mul_limbs(&a.0, &b.0, &mut c);
+    c
+}
+
 fn overflowing_sub_u256(a: U256, b: U256) -> (U256, bool) {
     let alo = ((a.0[1] as u128) << 64) | (a.0[0] as u128);
     let blo = ((b.0[1] as u128) << 64) | (b.0[0] as u128);
@@ -732,6 +924,246 @@ fn sub_u256(a: U256, b: U256) -> U256 {
     value
 }
 
+fn neg_u256(value: U256) -> U256 {
+    sub_u256(U256::default(), value)
+}
+
+fn is_neg_u256(value: U256) -> bool {
+    (value.0[3] as i64) < 0
+}
+
+/// Returns the number of significant limbs
+fn num_limbs(a: &[u64]) -> usize {
+    let mut n = a.len();
+    while (n > 0) && (a[n-1] == 0) {
+        n -= 1;
+    }
+    n
+}
+
+/// Knuth's algorithm D (TAOCP vol. 2, 4.3.1) on limb slices of any length,
+/// `b` must not be zero. `q` receives the low limbs of the quotient that fit
+/// and `r` the remainder, it must hold the significant limbs of `b`
+fn divrem_limbs(a: &[u64], b: &[u64], q: &mut [u64], r: &mut [u64]) {
+    let n = num_limbs(b);
+    let m = num_limbs(a);
+    assert!(n > 0);
+    for x in q.iter_mut() {
+        *x = 0;
+    }
+    for x in r.iter_mut() {
+        *x = 0;
+    }
+    if m < n {
+        r[..m].copy_from_slice(&a[..m]);
+        return;
+    }
+    if n == 1 {
+        // short division by a single limb
+        let b = b[0] as u128;
+        let mut rem: u128 = 0;
+        for i in (0..m).rev() {
+            let num = (rem << 64) | (a[i] as u128);
+            if i < q.len() {
+                q[i] = (num / b) as u64;
+            }
+            rem = num % b;
+        }
+        r[0] = rem as u64;
+        return;
+    }
+    // normalize so that the top bit of the divisor is set, the operands of
+    // the U256 operations fit on the stack
+    let s = b[n-1].leading_zeros();
+    let mut bn_stack: [u64; 8] = [0; 8];
+    let mut an_stack: [u64; 9] = [0; 9];
+    let mut bn_heap: Vec<u64>;
+    let mut an_heap: Vec<u64>;
+    let (bn, an): (&mut [u64], &mut [u64]) = if m <= 8 {
+        (&mut bn_stack[..n], &mut an_stack[..m+1])
+    } else {
+        bn_heap = vec![0; n];
+        an_heap = vec![0; m + 1];
+        (&mut bn_heap, &mut an_heap)
+    };
+    for i in (1..n).rev() {
+        bn[i] = (b[i] << s) | (((b[i-1] as u128) << s) >> 64) as u64;
+    }
+    bn[0] = b[0] << s;
+    an[m] = (((a[m-1] as u128) << s) >> 64) as u64;
+    for i in (1..m).rev() {
+        an[i] = (a[i] << s) | (((a[i-1] as u128) << s) >> 64) as u64;
+    }
+    an[0] = a[0] << s;
+    //
+    let bhi = bn[n-1] as u128;
+    let bnext = bn[n-2] as u128;
+    let mut j = m - n + 1;
+    while j > 0 {
+        j -= 1;
+        // estimate the quotient digit, it is at most off by two
+        let num = ((an[j+n] as u128) << 64) | (an[j+n-1] as u128);
+        let mut qhat = num / bhi;
+        let mut rhat = num % bhi;
+        while (qhat >> 64 != 0) || (qhat * bnext > ((rhat << 64) | (an[j+n-2] as u128))) {
+            qhat -= 1;
+            rhat += bhi;
+            if rhat >> 64 != 0 {
+                break;
+            }
+        }
+        // multiply and subtract
+        let mut carry: u64 = 0;
+        let mut borrow: u64 = 0;
+        for i in 0..n {
+            let p = mul_u64(qhat as u64, bn[i]) + (carry as u128);
+            carry = (p >> 64) as u64;
+            let (t0, b0) = an[i+j].overflowing_sub(p as u64);
+            let (t1, b1) = t0.overflowing_sub(borrow);
+            an[i+j] = t1;
+            borrow = (b0 as u64) + (b1 as u64);
+        }
+        let (t0, b0) = an[j+n].overflowing_sub(carry);
+        let (t1, b1) = t0.overflowing_sub(borrow);
+        an[j+n] = t1;
+        if b0 | b1 {
+            // estimate was one too large, add back
+            qhat -= 1;
+            let mut carry: u128 = 0;
+            for i in 0..n {
+                let t = (an[i+j] as u128) + (bn[i] as u128) + carry;
+                an[i+j] = t as u64;
+                carry = t >> 64;
+            }
+            an[j+n] = an[j+n].wrapping_add(carry as u64);
+        }
+        if j < q.len() {
+            q[j] = qhat as u64;
+        }
+    }
+    // unnormalize the remainder
+    for i in 0..n {
+        let lo = an[i] >> s;
+        let hi = (((an[i+1] as u128) << 64) >> s) as u64;
+        r[i] = lo | hi;
+    }
+}
+
+fn divrem_u256(a: U256, b: U256) -> (U256, U256) {
+    if !gt_u256(b, a) {
+        if b.le_u128() {
+            if a.le_u128() {
+                let a = a.low_u128();
+                let b = b.low_u128();
+                return (U256::from_u128(a / b), U256::from_u128(a % b));
+            }
+            if b.le_u64() {
+                // short division by a single limb
+                let b = b.low_u64() as u128;
+                let mut q = U256::default();
+                let mut r: u128 = 0;
+                for i in (0..4).rev() {
+                    let num = (r << 64) | (a.0[i] as u128);
+                    q.0[i] = (num / b) as u64;
+                    r = num % b;
+                }
+                return (q, U256::from_u64(r as u64));
+            }
+        }
+        let mut q = U256::default();
+        let mut r = U256::default();
+        divrem_limbs(&a.0, &b.0, &mut q.0, &mut r.0);
+        (q, r)
+    } else {
+        (U256::default(), a)
+    }
+}
+
+/// Reduces a number of any number of limbs modulo `b`
+fn mod_limbs(a: &[u64], b: U256) -> U256 {
+    let mut r = U256::default();
+    if !b.is_zero() {
+        divrem_limbs(a, &b.0, &mut [], &mut r.0);
+    }
+    r
+}
+
+fn addmod_u256(a: U256, b: U256, c: U256) -> U256 {
+    let (sum, carry) = overflowing_add_u256(a, b);
+    let temp = [sum.0[0], sum.0[1], sum.0[2], sum.0[3], carry as u64];
+    mod_limbs(&temp, c)
+}
+
+fn mulmod_u256(a: U256, b: U256, c: U256) -> U256 {
+    if a.le_u64() & b.le_u64() & c.le_u128() {
+        if c.is_zero() {
+            return U256::default();
+        }
+        let temp = mul_u64(a.low_u64(), b.low_u64());
+        return U256::from_u128(temp % c.low_u128());
+    }
+    let temp = full_mul_u256(a, b);
+    mod_limbs(&temp, c)
+}
+
+/// Exponentiation by squaring, scanning the exponent from its lowest bit
+fn exp_u256(base: U256, exponent: U256) -> U256 {
+    let mut result = U256::from_u64(1);
+    let mut base = base;
+    let num_bits = exponent.bits();
+    for i in 0..num_bits {
+        if (exponent.0[(i / 64) as usize] >> (i % 64)) & 1 != 0 {
+            result = mul_u256(result, base);
+        }
+        if i + 1 < num_bits {
+            base = mul_u256(base, base);
+        }
+    }
+    result
+}
+
+fn div_u256(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::default();
+    }
+    let (q, _) = divrem_u256(a, b);
+    q
+}
+
+fn mod_u256(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::default();
+    }
+    let (_, r) = divrem_u256(a, b);
+    r
+}
+
+/// Signed division, note that -2^255 / -1 naturally wraps to -2^255
+fn sdiv_u256(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::default();
+    }
+    let aneg = is_neg_u256(a);
+    let bneg = is_neg_u256(b);
+    let a = if aneg { neg_u256(a) } else { a };
+    let b = if bneg { neg_u256(b) } else { b };
+    let (q, _) = divrem_u256(a, b);
+    if aneg ^ bneg { neg_u256(q) } else { q }
+}
+
+/// Signed modulo, the result takes the sign of the dividend
+fn smod_u256(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::default();
+    }
+    let aneg = is_neg_u256(a);
+    let bneg = is_neg_u256(b);
+    let a = if aneg { neg_u256(a) } else { a };
+    let b = if bneg { neg_u256(b) } else { b };
+    let (_, r) = divrem_u256(a, b);
+    if aneg { neg_u256(r) } else { r }
+}
+
 fn gt_u256(a: U256, b: U256) -> bool {
     let alo = ((a.0[1] as u128) << 64) | (a.0[0] as u128);
     let blo = ((b.0[1] as u128) << 64) | (b.0[0] as u128);
@@ -742,6 +1174,17 @@ fn gt_u256(a: U256, b: U256) -> bool {
 
 struct VmStackSlots([U256; VmStack::LEN]);
 
+/// Allocates a zeroed `T` directly on the heap, without going through the
+/// stack as `Box::new` may do
+unsafe fn alloc_zeroed_box<T>() -> Box<T> {
+    let layout = std::alloc::Layout::new::<T>();
+    let ptr = std::alloc::alloc_zeroed(layout) as *mut T;
+    if ptr.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+    Box::from_raw(ptr)
+}
+
 struct VmStack {
     start: *const U256,
     sp: *mut U256,
@@ -806,6 +1249,10 @@ struct VmMemory {
     pub len: usize
 }
 
+pub(crate) fn num_words(num_bytes: u64) -> u64 {
+    num_bytes.div_ceil(32)
+}
+
 fn memory_gas_cost(memory_gas: u64, num_words: u64) -> u128 {
     mul_u64(memory_gas, num_words) + mul_u64(num_words, num_words) / 512
 }
@@ -819,6 +1266,52 @@ fn memory_extend_gas_cost(memory_gas: u64, num_words: u64, new_num_words: u64) -
     delta
 }
 
+/// Returns the gas cost and the refund of SSTORE changing a slot from
+/// `current` to `new`, `original` being its value before the transaction
+fn sstore_gas_cost(schedule: &Schedule, original: U256, current: U256, new: U256) -> (u64, i64) {
+    let sload_gas = Fee::Sload.gas(schedule) as u64;
+    let set_gas = schedule.sstore_set_gas;
+    let reset_gas = schedule.sstore_reset_gas;
+    let clears_refund = schedule.sstore_clears_refund;
+    if schedule.sstore_metering == SstoreMetering::Legacy {
+        if current.is_zero() & !new.is_zero() {
+            return (set_gas, 0);
+        }
+        let refund = if !current.is_zero() & new.is_zero() { clears_refund } else { 0 };
+        return (reset_gas, refund);
+    }
+    // net gas metering, no-op
+    if current == new {
+        return (sload_gas, 0);
+    }
+    // fresh slot
+    if original == current {
+        if original.is_zero() {
+            return (set_gas, 0);
+        }
+        let refund = if new.is_zero() { clears_refund } else { 0 };
+        return (reset_gas, refund);
+    }
+    // dirty slot
+    let mut refund: i64 = 0;
+    if !original.is_zero() {
+        if current.is_zero() {
+            refund -= clears_refund;
+        }
+        if new.is_zero() {
+            refund += clears_refund;
+        }
+    }
+    if original == new {
+        if original.is_zero() {
+            refund += (set_gas - sload_gas) as i64;
+        } else {
+            refund += (reset_gas - sload_gas) as i64;
+        }
+    }
+    (sload_gas, refund)
+}
+
 macro_rules! unsupported_gas {
     () => {
         panic!("unsupported gas amount")
@@ -855,8 +1348,8 @@ impl VmMemory {
         result
     }
 
-    fn init(&mut self, gas_limit: U256) {
-        let max_len = self.find_max_mem_words(gas_limit, &Schedule::default());
+    fn init(&mut self, gas_limit: U256, schedule: &Schedule) {
+        let max_len = self.find_max_mem_words(gas_limit, schedule);
         let (num_bytes, overflow) = max_len.overflowing_mul(32);
         if overflow {
             unsupported_gas!();
@@ -899,6 +1392,27 @@ impl VmMemory {
     unsafe fn slice(&self, offset: isize, size: usize) -> &[u8] {
         std::slice::from_raw_parts(self.ptr.offset(offset), size)
     }
+
+    /// Copies `size` bytes of `data` starting at `data_offset`, bytes read
+    /// past the end of `data` are zeros
+    unsafe fn copy_padded(&mut self, offset: usize, data: &[u8], data_offset: U256, size: usize) {
+        let dest = std::slice::from_raw_parts_mut(self.ptr.offset(offset as isize), size);
+        let start = if data_offset.le_u64() {
+            std::cmp::min(data_offset.low_u64(), data.len() as u64) as usize
+        } else {
+            data.len()
+        };
+        let count = std::cmp::min(data.len() - start, size);
+        dest[..count].copy_from_slice(&data[start..start+count]);
+        for b in &mut dest[count..] {
+            *b = 0;
+        }
+    }
+
+    /// Copies `size` bytes from `src` to `dest`, the ranges may overlap
+    unsafe fn copy_within(&mut self, dest: usize, src: usize, size: usize) {
+        std::ptr::copy(self.ptr.offset(src as isize), self.ptr.offset(dest as isize), size);
+    }
 }
 
 macro_rules! comment {
@@ -1024,7 +1538,7 @@ unsafe fn overflowing_sub_word_u128(value: Word, amount: u128) -> (Word, bool) {
         let valuelo = (value1 as u128) << 64 | (value0 as u128);
         let valuehi = (value3 as u128) << 64 | (value2 as u128);
         let (templo, borrowlo) = valuelo.overflowing_sub(amount);
-        let (temphi, borrowhi) = valuelo.overflowing_sub(borrowlo as u128);
+        let (temphi, borrowhi) = valuehi.overflowing_sub(borrowlo as u128);
         let temp0 = templo as u64;
         let temp1 = (templo >> 64) as u64;
         let temp2 = temphi as u64;
@@ -1085,6 +1599,28 @@ macro_rules! metered_extend {
     }
 }
 
+macro_rules! metered_charge {
+    ($cost:expr, $gas:ident, $error:ident) => {
+        let (newgas, oog) = overflowing_sub_word($gas, $cost);
+        $gas = newgas;
+        if oog {
+            $error = VmError::OutOfGas;
+            break;
+        }
+    }
+}
+
+/// Charges the cold surcharge if `address` was not accessed yet (EIP-2929),
+/// the warm cost being part of the static fee
+macro_rules! access_account {
+    ($address:ident, $schedule:ident, $host:ident, $gas:ident, $error:ident) => {
+        if $schedule.access_lists && $host.access.access_address(&$address) {
+            let cost = $schedule.cold_account_access_gas - $schedule.warm_storage_read_gas;
+            metered_charge!(cost, $gas, $error);
+        }
+    }
+}
+
 macro_rules! extend_memory {
     ($offset:ident, $size:literal, $schedule:ident, $memory:ident, $gas:ident, $error:ident) => {
         if $offset.le_u64() {
@@ -1099,35 +1635,301 @@ macro_rules! extend_memory {
         }
     };
     ($offset:ident, $size:ident, $schedule:ident, $memory:ident, $gas:ident, $error:ident) => {
-        if $offset.le_u64() & $size.le_u64() {
-            let (new_len, overflow) = {
-                let (temp1, overflow1) = $offset.low_u64().overflowing_add($size.low_u64());
-                let (temp2, overflow2) = temp1.overflowing_add(31);
-                (temp2 / 32, overflow2 | overflow2)
-            };
-            metered_extend!(new_len, overflow, $schedule, $memory, $gas, $error);
-        } else {
-            $error = VmError::OutOfGas;
-            break;
+        // a zero sized access never extends memory whatever the offset
+        if !$size.is_zero() {
+            if $offset.le_u64() & $size.le_u64() {
+                let (new_len, overflow) = {
+                    let (temp1, overflow1) = $offset.low_u64().overflowing_add($size.low_u64());
+                    let (temp2, overflow2) = temp1.overflowing_add(31);
+                    (temp2 / 32, overflow1 | overflow2)
+                };
+                metered_extend!(new_len, overflow, $schedule, $memory, $gas, $error);
+            } else {
+                $error = VmError::OutOfGas;
+                break;
+            }
         }
     }
 }
 
+/// Event emitted by the LOGN opcodes
+#[derive(Debug)]
+pub struct Log {
+    address: Address,
+    topics: Vec<U256>,
+    data: Vec<u8>
+}
+
+/// How the execution ended
+#[derive(Debug)]
+pub enum VmStatus {
+    Success,
+    /// REVERT, state changes are undone but unused gas is returned
+    Revert,
+    /// Exceptional halt, all gas is consumed
+    Halt(VmError),
+}
+
 #[derive(Debug)]
 pub struct ReturnData {
+    status: VmStatus,
     offset: usize,
     size: usize,
-    gas: u64
+    gas: u64,
+    /// Gas refund accumulated by SSTORE, can be negative
+    refund: i64,
+    logs: Vec<Log>
 }
 
 impl ReturnData {
-    pub fn new(offset: usize, size: usize, gas: u64) -> Self {
-        ReturnData {
-            offset: offset,
-            size: size,
-            gas: gas
+    pub fn new(status: VmStatus, offset: usize, size: usize, gas: u64, refund: i64, logs: Vec<Log>) -> Self {
+        ReturnData { status, offset, size, gas, refund, logs }
+    }
+
+    pub fn halt(error: VmError) -> Self {
+        ReturnData::new(VmStatus::Halt(error), 0, 0, 0, 0, Vec::new())
+    }
+}
+
+/// Parameters of the message being executed and of its transaction
+struct VmContext {
+    /// Account whose code is executing
+    pub address: Address,
+    pub caller: Address,
+    pub origin: Address,
+    pub value: U256,
+    pub gas_price: U256,
+    pub input: Vec<u8>,
+    /// Forbids state modifications (STATICCALL)
+    pub is_static: bool,
+    /// Number of enclosing frames, 0 for the transaction itself
+    pub depth: usize,
+}
+
+/// World state and block information shared by the frames of a transaction
+struct VmHost<'a> {
+    pub env: &'a BlockEnv,
+    pub hashes: &'a dyn BlockHashes,
+    pub schedule: &'a Schedule,
+    pub state: &'a mut dyn AccountState,
+    pub storage: &'a mut dyn Storage,
+    pub transient: TransientStorage,
+    pub access: AccessSet,
+}
+
+#[derive(Copy, Clone)]
+struct VmCheckpoint {
+    state: usize,
+    storage: usize,
+    transient: usize,
+    access: usize,
+}
+
+impl<'a> VmHost<'a> {
+    /// Returns a checkpoint to revert to when a frame fails
+    fn checkpoint(&self) -> VmCheckpoint {
+        VmCheckpoint {
+            state: self.state.checkpoint(),
+            storage: self.storage.checkpoint(),
+            transient: self.transient.checkpoint(),
+            access: self.access.checkpoint(),
+        }
+    }
+
+    /// Undoes the modifications made since `checkpoint`
+    fn revert(&mut self, checkpoint: VmCheckpoint) {
+        self.state.revert(checkpoint.state);
+        self.storage.revert(checkpoint.storage);
+        self.transient.revert(checkpoint.transient);
+        self.access.revert(checkpoint.access);
+    }
+}
+
+/// Moves `value` from `from` to `to`, returns false if the balance of `from`
+/// is insufficient
+fn transfer(state: &mut dyn AccountState, from: &Address, to: &Address, value: U256) -> bool {
+    let (balance, insufficient) = overflowing_sub_u256(state.balance(from), value);
+    if insufficient {
+        return false;
+    }
+    if !value.is_zero() {
+        state.account_mut(from).balance = balance;
+        let account = state.account_mut(to);
+        account.balance = add_u256(account.balance, value);
+    }
+    true
+}
+
+/// Executes the code at `code_address` in a new frame, `value` being moved
+/// from the caller to the callee first if `transfers_value` is set. Returns
+/// the outcome of the frame and its output.
+unsafe fn call_frame(context: &VmContext, code_address: &Address, transfers_value: bool, host: &mut VmHost, gas: u64) -> (ReturnData, Vec<u8>) {
+    // failing before execution returns all the gas
+    let failure = ReturnData::new(VmStatus::Revert, 0, 0, gas, 0, Vec::new());
+    if context.depth > VM_MAX_DEPTH {
+        return (failure, Vec::new());
+    }
+    let checkpoint = host.checkpoint();
+    if transfers_value && !transfer(host.state, &context.caller, &context.address, context.value) {
+        return (failure, Vec::new());
+    }
+    let (result, output) = match precompiles::precompile(code_address, host.schedule.fork) {
+        Some(precompile) => run_precompile(precompile, context, host, gas),
+        None => {
+            let code = host.state.code(code_address).to_vec();
+            run_frame(&code, context, host, gas)
+        }
+    };
+    match result.status {
+        VmStatus::Success => {},
+        _ => host.revert(checkpoint)
+    }
+    (result, output)
+}
+
+/// Stack left below which a frame runs on a new stack segment, a frame of
+/// `run_evm` takes a few KB (tens of KB in debug builds)
+const VM_STACK_RED_ZONE: usize = 256 * 1024;
+
+/// Size of the stack segments allocated for nested frames
+const VM_STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Runs `code` in a fresh ROM and memory. Returns the outcome of the frame
+/// and its output.
+unsafe fn run_frame(code: &[u8], context: &VmContext, host: &mut VmHost, gas: u64) -> (ReturnData, Vec<u8>) {
+    if code.is_empty() {
+        return (ReturnData::new(VmStatus::Success, 0, 0, gas, 0, Vec::new()), Vec::new());
+    }
+    let mut rom = VmRom::new();
+    rom.init(code, host.schedule);
+    let mut memory = VmMemory::new();
+    memory.init(U256::from_u64(gas), host.schedule);
+    // nested frames recurse through here, up to the max call depth
+    let result = stacker::maybe_grow(VM_STACK_RED_ZONE, VM_STACK_SEGMENT_SIZE, || {
+        run_evm(code, context, &rom, host, U256::from_u64(gas), &mut memory)
+    });
+    let output = memory.slice(result.offset as isize, result.size).to_vec();
+    (result, output)
+}
+
+/// Runs a precompiled contract on the input of `context`. Returns the outcome
+/// and the output like `run_frame`.
+fn run_precompile(precompile: &precompiles::Precompile, context: &VmContext, host: &VmHost, gas: u64) -> (ReturnData, Vec<u8>) {
+    let cost = (precompile.gas)(&context.input, host.schedule);
+    if cost > gas {
+        return (ReturnData::halt(VmError::OutOfGas), Vec::new());
+    }
+    match (precompile.run)(&context.input) {
+        Some(output) => {
+            let result = ReturnData::new(VmStatus::Success, 0, output.len(), gas - cost, 0, Vec::new());
+            (result, output)
+        },
+        None => (ReturnData::halt(VmError::PrecompileFailure), Vec::new())
+    }
+}
+
+/// Address of a contract created with CREATE, the last 20 bytes of the
+/// hash of `rlp([sender, nonce])`
+fn create_address(sender: &Address, nonce: u64) -> Address {
+    let nonce_bytes = nonce.to_be_bytes();
+    let nonce_bytes = &nonce_bytes[(nonce.leading_zeros() / 8) as usize..];
+    let mut payload: Vec<u8> = Vec::with_capacity(31);
+    payload.push(0x80 + 20);
+    payload.extend_from_slice(sender.as_bytes());
+    if (nonce_bytes.len() == 1) && (nonce_bytes[0] < 0x80) {
+        payload.push(nonce_bytes[0]);
+    } else {
+        payload.push(0x80 + nonce_bytes.len() as u8);
+        payload.extend_from_slice(nonce_bytes);
+    }
+    let mut rlp: Vec<u8> = Vec::with_capacity(32);
+    rlp.push(0xc0 + payload.len() as u8);
+    rlp.extend_from_slice(&payload);
+    Address::from_slice(&keccak256(&rlp)[12..])
+}
+
+/// Address of a contract created with CREATE2 (EIP-1014), the last 20 bytes
+/// of `keccak256(0xff ++ sender ++ salt ++ keccak256(initcode))`
+fn create2_address(sender: &Address, salt: U256, initcode: &[u8]) -> Address {
+    let mut preimage: Vec<u8> = Vec::with_capacity(85);
+    preimage.push(0xff);
+    preimage.extend_from_slice(sender.as_bytes());
+    preimage.extend_from_slice(&salt.to_be_bytes());
+    preimage.extend_from_slice(&keccak256(initcode));
+    Address::from_slice(&keccak256(&preimage)[12..])
+}
+
+/// Deploys a contract from `initcode`, run in a new frame on behalf of
+/// `context.address`. The address comes from CREATE2 when `salt` is given.
+/// Returns the outcome of the frame, its output and the new address.
+unsafe fn create_frame(context: &VmContext, initcode: &[u8], value: U256, salt: Option<U256>, host: &mut VmHost, gas: u64) -> (ReturnData, Vec<u8>, Address) {
+    let schedule = host.schedule;
+    let sender = context.address;
+    // failing before execution returns all the gas
+    let failure = ReturnData::new(VmStatus::Revert, 0, 0, gas, 0, Vec::new());
+    let nonce = host.state.account(&sender).map_or(0, |account| account.nonce);
+    let (_, insufficient) = overflowing_sub_u256(host.state.balance(&sender), value);
+    if (context.depth + 1 > VM_MAX_DEPTH) | insufficient | (nonce == u64::MAX) {
+        return (failure, Vec::new(), Address::default());
+    }
+    host.state.account_mut(&sender).nonce = nonce + 1;
+    let address = match salt {
+        Some(salt) => create2_address(&sender, salt, initcode),
+        None => create_address(&sender, nonce)
+    };
+    if schedule.access_lists {
+        host.access.access_address(&address);
+    }
+    let collision = host.state.account(&address)
+        .is_some_and(|account| account.nonce != 0 || !account.code.is_empty());
+    if collision {
+        return (ReturnData::halt(VmError::AddressCollision), Vec::new(), address);
+    }
+    let checkpoint = host.checkpoint();
+    // EIP-161 starts new contracts at nonce 1
+    host.state.account_mut(&address).nonce = if schedule.fork >= Fork::Spurious { 1 } else { 0 };
+    host.state.mark_created(&address);
+    transfer(host.state, &sender, &address, value);
+    let child_context = VmContext {
+        address,
+        caller: sender,
+        origin: context.origin,
+        value,
+        gas_price: context.gas_price,
+        input: Vec::new(),
+        is_static: false,
+        depth: context.depth + 1,
+    };
+    let (mut result, output) = run_frame(initcode, &child_context, host, gas);
+    match result.status {
+        VmStatus::Success => {},
+        _ => {
+            host.revert(checkpoint);
+            return (result, output, address);
+        }
+    }
+    let deploy_error = if output.len() > schedule.max_code_size {
+        VmError::CodeSizeExceeded
+    } else if (schedule.fork >= Fork::London) & (output.first() == Some(&0xef)) {
+        VmError::InvalidCode
+    } else {
+        VmError::None
+    };
+    if let VmError::None = deploy_error {
+        let deposit = schedule.create_data_gas * output.len() as u64;
+        if deposit <= result.gas {
+            result.gas -= deposit;
+            host.state.account_mut(&address).code = output.clone();
+        } else if schedule.fork >= Fork::Homestead {
+            host.revert(checkpoint);
+            return (ReturnData::halt(VmError::OutOfGas), Vec::new(), address);
         }
+        // before EIP-2 a failed deposit leaves an empty contract
+    } else {
+        host.revert(checkpoint);
+        return (ReturnData::halt(deploy_error), Vec::new(), address);
     }
+    (result, output, address)
 }
 
 fn lldb_hook_single_step(pc: usize, gas: u64, stsize: usize) {}
@@ -1145,19 +1947,28 @@ macro_rules! lldb_hook {
     }
 }
 
-unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit: U256, memory: &mut VmMemory) -> ReturnData {
-    // TODO: use MaybeUninit
-    let mut slots: VmStackSlots = std::mem::uninitialized();
+unsafe fn run_evm(bytecode: &[u8], context: &VmContext, rom: &VmRom, host: &mut VmHost, gas_limit: U256, memory: &mut VmMemory) -> ReturnData {
+    let schedule = host.schedule;
+    let env = host.env;
+    let hashes = host.hashes;
+    // slots live on the heap to keep nested frames small
+    let mut slots: Box<VmStackSlots> = alloc_zeroed_box();
     let mut stack: VmStack = VmStack::new(&mut slots);
     let code: *const Opcode = rom.code() as *const Opcode;
     let mut pc: usize = 0;
     let mut gas: Word = Word::from_slice(&(gas_limit.0));
     let mut error: VmError = VmError::None;
+    let mut refund: i64 = 0;
+    let mut logs: Vec<Log> = Vec::new();
+    let input: &[u8] = &context.input;
+    let checkpoint = host.checkpoint();
+    // output of the last sub-call
+    let mut return_data: Vec<u8> = Vec::new();
     let mut entered = false;
     while !entered {
         entered = true;
         check_exception_at!(0, gas, rom, stack, error);
-        panic!("{:?}", error);
+        return ReturnData::halt(error);
     }
     loop {
         let opcode = *code.offset(pc as isize);
@@ -1195,6 +2006,74 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 //
                 pc += 1;
             }
+            DIV => {
+                comment!("opDIV");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                let result = div_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            SDIV => {
+                comment!("opSDIV");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                let result = sdiv_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            MOD => {
+                comment!("opMOD");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                let result = mod_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            SMOD => {
+                comment!("opSMOD");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                let result = smod_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            ADDMOD => {
+                comment!("opADDMOD");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                let c = stack.pop_u256();
+                let result = addmod_u256(a, b, c);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            MULMOD => {
+                comment!("opMULMOD");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                let c = stack.pop_u256();
+                let result = mulmod_u256(a, b, c);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            EXP => {
+                comment!("opEXP");
+                let a = stack.pop_u256();
+                let b = stack.pop_u256();
+                // the static part is already accounted in the basic block cost
+                let num_bytes = (b.bits() as u64).div_ceil(8);
+                metered_charge!(schedule.exp_byte_gas * num_bytes, gas, error);
+                let result = exp_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
             SIGNEXTEND => {
                 comment!("opSIGNEXTEND");
                 let offset = *(stack.sp as *const u32) % 32;
@@ -1207,6 +2086,15 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 //
                 pc += 1;
             }
+            LT => {
+                comment!("opLT");
+                let a = stack.pop();
+                let b = stack.pop();
+                let result = U256::from_u64(lt_u256(a, b) as u64);
+                stack.push(result);
+                //
+                pc += 1;
+            }
             GT => {
                 comment!("opGT");
                 let a = stack.pop_u256();
@@ -1216,6 +2104,24 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 //
                 pc += 1;
             }
+            SLT => {
+                comment!("opSLT");
+                let a = stack.pop();
+                let b = stack.pop();
+                let result = U256::from_u64(slt_u256(a, b) as u64);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            SGT => {
+                comment!("opSGT");
+                let a = stack.pop();
+                let b = stack.pop();
+                let result = U256::from_u64(sgt_u256(a, b) as u64);
+                stack.push(result);
+                //
+                pc += 1;
+            }
             EQ => {
                 comment!("opEQ");
                 let a = stack.pop();
@@ -1292,12 +2198,281 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 //
                 pc += 1;
             }
+            SHR => {
+                comment!("opSHR");
+                let a = stack.pop();
+                let b = stack.pop();
+                let result = shr_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            SAR => {
+                comment!("opSAR");
+                let a = stack.pop();
+                let b = stack.pop();
+                let result = sar_u256(a, b);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            SHA3 => {
+                comment!("opSHA3");
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                extend_memory!(offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                metered_charge!(schedule.sha3_word_gas * num_words(size), gas, error);
+                let data = if size > 0 {
+                    memory.slice(offset.low_u64() as isize, size as usize)
+                } else {
+                    &[]
+                };
+                let result = U256::from_be_bytes(&keccak256(data));
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            ADDRESS => {
+                comment!("opADDRESS");
+                stack.push(U256::from_address(&context.address));
+                //
+                pc += 1;
+            }
+            BALANCE => {
+                comment!("opBALANCE");
+                let address = stack.pop_u256().to_address();
+                access_account!(address, schedule, host, gas, error);
+                stack.push(host.state.balance(&address));
+                //
+                pc += 1;
+            }
+            ORIGIN => {
+                comment!("opORIGIN");
+                stack.push(U256::from_address(&context.origin));
+                //
+                pc += 1;
+            }
+            CALLER => {
+                comment!("opCALLER");
+                stack.push(U256::from_address(&context.caller));
+                //
+                pc += 1;
+            }
+            CALLVALUE => {
+                comment!("opCALLVALUE");
+                stack.push(context.value);
+                //
+                pc += 1;
+            }
+            CALLDATALOAD => {
+                comment!("opCALLDATALOAD");
+                let offset = stack.pop_u256();
+                let low = offset.low_u64();
+                let result = if offset.le_u64() & (low < input.len() as u64) {
+                    let low = low as usize;
+                    if (low + 32) <= input.len() {
+                        let src = input.as_ptr().offset(low as isize);
+                        bswap_u256(loadu_u256(src as *const U256, 0))
+                    } else {
+                        let mut temp: [u8; 32] = [0; 32];
+                        let count = input.len() - low;
+                        temp[..count].copy_from_slice(&input[low..]);
+                        U256::from_be_bytes(&temp)
+                    }
+                } else {
+                    U256::default()
+                };
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            CALLDATASIZE => {
+                comment!("opCALLDATASIZE");
+                stack.push(U256::from_u64(input.len() as u64));
+                //
+                pc += 1;
+            }
+            CALLDATACOPY => {
+                comment!("opCALLDATACOPY");
+                let dest_offset = stack.pop_u256();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                extend_memory!(dest_offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                metered_charge!((Copy.gas(schedule) as u64) * num_words(size), gas, error);
+                if size > 0 {
+                    memory.copy_padded(dest_offset.low_u64() as usize, input, offset, size as usize);
+                }
+                //
+                pc += 1;
+            }
             CODESIZE => {
                 comment!("opCODESIZE");
                 stack.push(U256::from_u64(bytecode.len() as u64));
                 //
                 pc += 1;
             }
+            CODECOPY => {
+                comment!("opCODECOPY");
+                let dest_offset = stack.pop_u256();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                extend_memory!(dest_offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                metered_charge!((Copy.gas(schedule) as u64) * num_words(size), gas, error);
+                if size > 0 {
+                    memory.copy_padded(dest_offset.low_u64() as usize, bytecode, offset, size as usize);
+                }
+                //
+                pc += 1;
+            }
+            GASPRICE => {
+                comment!("opGASPRICE");
+                stack.push(context.gas_price);
+                //
+                pc += 1;
+            }
+            EXTCODESIZE => {
+                comment!("opEXTCODESIZE");
+                let address = stack.pop_u256().to_address();
+                access_account!(address, schedule, host, gas, error);
+                let result = U256::from_u64(host.state.code(&address).len() as u64);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            EXTCODECOPY => {
+                comment!("opEXTCODECOPY");
+                let address = stack.pop_u256().to_address();
+                let dest_offset = stack.pop_u256();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                access_account!(address, schedule, host, gas, error);
+                extend_memory!(dest_offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                metered_charge!((Copy.gas(schedule) as u64) * num_words(size), gas, error);
+                if size > 0 {
+                    let code = host.state.code(&address);
+                    memory.copy_padded(dest_offset.low_u64() as usize, code, offset, size as usize);
+                }
+                //
+                pc += 1;
+            }
+            RETURNDATASIZE => {
+                comment!("opRETURNDATASIZE");
+                stack.push(U256::from_u64(return_data.len() as u64));
+                //
+                pc += 1;
+            }
+            RETURNDATACOPY => {
+                comment!("opRETURNDATACOPY");
+                let dest_offset = stack.pop_u256();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                // unlike other copies, reading past the end is an exception
+                let end = if offset.le_u64() & size.le_u64() {
+                    offset.low_u64().checked_add(size.low_u64())
+                } else {
+                    None
+                };
+                let out_of_bounds = match end {
+                    Some(end) => end > return_data.len() as u64,
+                    None => true
+                };
+                if out_of_bounds {
+                    error = VmError::ReturnDataOutOfBounds;
+                    break;
+                }
+                extend_memory!(dest_offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                metered_charge!((Copy.gas(schedule) as u64) * num_words(size), gas, error);
+                if size > 0 {
+                    memory.copy_padded(dest_offset.low_u64() as usize, &return_data, offset, size as usize);
+                }
+                //
+                pc += 1;
+            }
+            EXTCODEHASH => {
+                comment!("opEXTCODEHASH");
+                let address = stack.pop_u256().to_address();
+                access_account!(address, schedule, host, gas, error);
+                let result = host.state.code_hash(&address);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            BLOCKHASH => {
+                comment!("opBLOCKHASH");
+                let number = stack.pop_u256();
+                let current = env.number;
+                // only the 256 most recent complete blocks are available
+                let mut result = U256::default();
+                if number.le_u64() & current.le_u64() {
+                    let number = number.low_u64();
+                    let current = current.low_u64();
+                    if (number < current) && (current - number <= 256) {
+                        result = hashes.block_hash(number);
+                    }
+                }
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            COINBASE => {
+                comment!("opCOINBASE");
+                stack.push(U256::from_address(&env.coinbase));
+                //
+                pc += 1;
+            }
+            TIMESTAMP => {
+                comment!("opTIMESTAMP");
+                stack.push(env.timestamp);
+                //
+                pc += 1;
+            }
+            NUMBER => {
+                comment!("opNUMBER");
+                stack.push(env.number);
+                //
+                pc += 1;
+            }
+            DIFFICULTY => {
+                comment!("opDIFFICULTY");
+                // PREVRANDAO since the Merge (EIP-4399)
+                if schedule.fork >= Fork::Paris {
+                    stack.push(env.prev_randao);
+                }
+                else {
+                    stack.push(env.difficulty);
+                }
+                //
+                pc += 1;
+            }
+            GASLIMIT => {
+                comment!("opGASLIMIT");
+                stack.push(env.gas_limit);
+                //
+                pc += 1;
+            }
+            CHAINID => {
+                comment!("opCHAINID");
+                stack.push(env.chain_id);
+                //
+                pc += 1;
+            }
+            SELFBALANCE => {
+                comment!("opSELFBALANCE");
+                stack.push(host.state.balance(&context.address));
+                //
+                pc += 1;
+            }
+            BASEFEE => {
+                comment!("opBASEFEE");
+                stack.push(env.base_fee);
+                //
+                pc += 1;
+            }
             POP => {
                 comment!("opPOP");
                 stack.pop();
@@ -1331,10 +2506,72 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 //
                 pc += 1;
             },
+            SLOAD => {
+                comment!("opSLOAD");
+                let key = stack.pop_u256();
+                if schedule.access_lists && host.access.access_slot(&context.address, &key) {
+                    let cost = schedule.cold_sload_gas - schedule.warm_storage_read_gas;
+                    metered_charge!(cost, gas, error);
+                }
+                let result = host.storage.load(&context.address, &key);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            SSTORE => {
+                comment!("opSSTORE");
+                if context.is_static {
+                    error = VmError::WriteProtection;
+                    break;
+                }
+                let key = stack.pop_u256();
+                let value = stack.pop_u256();
+                // SSTORE ends its basic block so gas is exact here
+                if schedule.sstore_metering == SstoreMetering::NetSentry {
+                    let gas_left = gas.as_u256();
+                    if gas_left.le_u64() & (gas_left.low_u64() <= schedule.sstore_sentry_gas) {
+                        error = VmError::OutOfGas;
+                        break;
+                    }
+                }
+                let original = host.storage.original(&context.address, &key);
+                let current = host.storage.load(&context.address, &key);
+                let (mut cost, delta) = sstore_gas_cost(schedule, original, current, value);
+                if schedule.access_lists && host.access.access_slot(&context.address, &key) {
+                    cost += schedule.cold_sload_gas;
+                }
+                metered_charge!(cost, gas, error);
+                refund += delta;
+                host.storage.store(&context.address, &key, value);
+                //
+                pc += 1;
+                check_exception_at!(pc as u64, gas, rom, stack, error);
+                break;
+            }
+            TLOAD => {
+                comment!("opTLOAD");
+                let key = stack.pop_u256();
+                let result = host.transient.load(&context.address, &key);
+                stack.push(result);
+                //
+                pc += 1;
+            }
+            TSTORE => {
+                comment!("opTSTORE");
+                if context.is_static {
+                    error = VmError::WriteProtection;
+                    break;
+                }
+                let key = stack.pop_u256();
+                let value = stack.pop_u256();
+                host.transient.store(&context.address, &key, value);
+                //
+                pc += 1;
+            }
             JUMP => {
                 comment!("opJUMP");
                 let addr = stack.pop();
-                let in_bounds = is_ltpow2_u256(addr, VmRom::MAX_CODESIZE);
+                let in_bounds = is_ltpow2_u256(addr, rom.code_size());
                 let low = addr.low_u64();
                 if in_bounds & rom.is_jumpdest(low) {
                     pc = low as usize + 1;
@@ -1356,7 +2593,7 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                     break;
                 }
                 else {
-                    let in_bounds = is_ltpow2_u256(addr, VmRom::MAX_CODESIZE);
+                    let in_bounds = is_ltpow2_u256(addr, rom.code_size());
                     let low = addr.low_u64();
                     if in_bounds & rom.is_jumpdest(low) {
                         pc = low as usize + 1;
@@ -1389,11 +2626,32 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 stack.push(result);
                 //
                 pc += 1;
-                check_exception_at!(pc as u64, gas, rom, stack, error);
-                break;
+                check_exception_at!(pc as u64, gas, rom, stack, error);
+                break;
+            }
+            JUMPDEST => {
+                comment!("opJUMPDEST");
+                //
+                pc += 1;
+            }
+            MCOPY => {
+                comment!("opMCOPY");
+                let dest_offset = stack.pop_u256();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                extend_memory!(dest_offset, size, schedule, memory, gas, error);
+                extend_memory!(offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                metered_charge!((Copy.gas(schedule) as u64) * num_words(size), gas, error);
+                if size > 0 {
+                    memory.copy_within(dest_offset.low_u64() as usize, offset.low_u64() as usize, size as usize);
+                }
+                //
+                pc += 1;
             }
-            JUMPDEST => {
-                comment!("opJUMPDEST");
+            PUSH0 => {
+                comment!("opPUSH0");
+                stack.push(U256::default());
                 //
                 pc += 1;
             }
@@ -1407,7 +2665,7 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
             }
             PUSH2 => {
                 comment!("opPUSH2");
-                let result = *(code.offset(pc as isize + 1) as *const u16);
+                let result = std::ptr::read_unaligned(code.offset(pc as isize + 1) as *const u16);
                 let result = U256::from_u64(result as u64);
                 stack.push(result);
                 //
@@ -1415,7 +2673,7 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
             }
             PUSH4 => {
                 comment!("opPUSH4");
-                let result = *(code.offset(pc as isize + 1) as *const u32);
+                let result = std::ptr::read_unaligned(code.offset(pc as isize + 1) as *const u32);
                 let result = U256::from_u64(result as u64);
                 stack.push(result);
                 //
@@ -1492,13 +2750,235 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
                 //
                 pc += 1;
             }
+            LOG0 | LOG1 | LOG2 | LOG3 | LOG4 => {
+                comment!("opLOGn");
+                if context.is_static {
+                    error = VmError::WriteProtection;
+                    break;
+                }
+                let num_topics = opcode.log_index();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                let mut topics: Vec<U256> = Vec::with_capacity(num_topics);
+                for _ in 0..num_topics {
+                    topics.push(stack.pop_u256());
+                }
+                extend_memory!(offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                let cost = (schedule.log_topic_gas * num_topics as u64) + (schedule.log_data_gas * size);
+                metered_charge!(cost, gas, error);
+                let data = if size > 0 {
+                    memory.slice(offset.low_u64() as isize, size as usize).to_vec()
+                } else {
+                    Vec::new()
+                };
+                logs.push(Log { address: context.address, topics, data });
+                //
+                pc += 1;
+            }
+            CALL | CALLCODE | DELEGATECALL | STATICCALL => {
+                comment!("opCALL");
+                let requested_gas = stack.pop_u256();
+                let address = stack.pop_u256().to_address();
+                let value = if (opcode == CALL) | (opcode == CALLCODE) {
+                    stack.pop_u256()
+                } else {
+                    U256::default()
+                };
+                let args_offset = stack.pop_u256();
+                let args_size = stack.pop_u256();
+                let ret_offset = stack.pop_u256();
+                let ret_size = stack.pop_u256();
+                let has_value = !value.is_zero();
+                if context.is_static & has_value & (opcode == CALL) {
+                    error = VmError::WriteProtection;
+                    break;
+                }
+                access_account!(address, schedule, host, gas, error);
+                extend_memory!(args_offset, args_size, schedule, memory, gas, error);
+                extend_memory!(ret_offset, ret_size, schedule, memory, gas, error);
+                let mut cost: u64 = 0;
+                if has_value {
+                    cost += schedule.call_value_gas;
+                }
+                if opcode == CALL {
+                    // since EIP-161 only value transfers create accounts
+                    let is_new = if schedule.fork >= Fork::Spurious {
+                        has_value & host.state.is_empty(&address)
+                    } else {
+                        !host.state.exists(&address)
+                    };
+                    if is_new {
+                        cost += schedule.new_account_gas;
+                    }
+                }
+                metered_charge!(cost, gas, error);
+                // CALL ends its basic block so gas is exact here
+                let requested_gas = requested_gas.saturating_u64();
+                let call_gas = if schedule.fork >= Fork::Tangerine {
+                    // all but one 64th of the remaining gas (EIP-150)
+                    let gas_left = gas.as_u256().saturating_u64();
+                    std::cmp::min(requested_gas, gas_left - gas_left / 64)
+                } else {
+                    requested_gas
+                };
+                metered_charge!(call_gas, gas, error);
+                let call_gas = if has_value { call_gas + schedule.call_stipend } else { call_gas };
+                let input = if args_size.is_zero() {
+                    Vec::new()
+                } else {
+                    memory.slice(args_offset.low_u64() as isize, args_size.low_u64() as usize).to_vec()
+                };
+                let child_context = VmContext {
+                    address: if (opcode == CALL) | (opcode == STATICCALL) { address } else { context.address },
+                    caller: if opcode == DELEGATECALL { context.caller } else { context.address },
+                    origin: context.origin,
+                    value: if opcode == DELEGATECALL { context.value } else { value },
+                    gas_price: context.gas_price,
+                    input,
+                    is_static: context.is_static | (opcode == STATICCALL),
+                    depth: context.depth + 1,
+                };
+                let transfers_value = (opcode == CALL) | (opcode == CALLCODE);
+                let (result, output) = call_frame(&child_context, &address, transfers_value, host, call_gas);
+                gas = Word::from_slice(&add_u256(gas.as_u256(), U256::from_u64(result.gas)).0);
+                let success = if let VmStatus::Success = result.status {
+                    logs.extend(result.logs);
+                    refund += result.refund;
+                    true
+                } else {
+                    false
+                };
+                let size = std::cmp::min(ret_size.low_u64(), output.len() as u64);
+                if size > 0 {
+                    memory.copy_padded(ret_offset.low_u64() as usize, &output, U256::default(), size as usize);
+                }
+                return_data = output;
+                stack.push(U256::from_u64(success as u64));
+                //
+                pc += 1;
+                check_exception_at!(pc as u64, gas, rom, stack, error);
+                break;
+            }
+            CREATE | CREATE2 => {
+                comment!("opCREATE");
+                if context.is_static {
+                    error = VmError::WriteProtection;
+                    break;
+                }
+                let value = stack.pop_u256();
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                let salt = if opcode == CREATE2 {
+                    Some(stack.pop_u256())
+                } else {
+                    None
+                };
+                extend_memory!(offset, size, schedule, memory, gas, error);
+                let size = size.low_u64();
+                if size > schedule.max_initcode_size as u64 {
+                    error = VmError::OutOfGas;
+                    break;
+                }
+                let mut cost = schedule.initcode_word_gas * num_words(size);
+                if opcode == CREATE2 {
+                    cost += schedule.sha3_word_gas * num_words(size);
+                }
+                metered_charge!(cost, gas, error);
+                let initcode = if size > 0 {
+                    memory.slice(offset.low_u64() as isize, size as usize).to_vec()
+                } else {
+                    Vec::new()
+                };
+                // CREATE ends its basic block so gas is exact here
+                let gas_left = gas.as_u256().saturating_u64();
+                let create_gas = if schedule.fork >= Fork::Tangerine {
+                    gas_left - gas_left / 64
+                } else {
+                    gas_left
+                };
+                metered_charge!(create_gas, gas, error);
+                let (result, output, address) = create_frame(context, &initcode, value, salt, host, create_gas);
+                gas = Word::from_slice(&add_u256(gas.as_u256(), U256::from_u64(result.gas)).0);
+                match result.status {
+                    VmStatus::Success => {
+                        logs.extend(result.logs);
+                        refund += result.refund;
+                        return_data = Vec::new();
+                        stack.push(U256::from_address(&address));
+                    }
+                    VmStatus::Revert => {
+                        return_data = output;
+                        stack.push(U256::default());
+                    }
+                    VmStatus::Halt(_) => {
+                        return_data = Vec::new();
+                        stack.push(U256::default());
+                    }
+                }
+                //
+                pc += 1;
+                check_exception_at!(pc as u64, gas, rom, stack, error);
+                break;
+            }
             RETURN => {
                 lldb_hook!(pc, gas, stack, lldb_hook_stop);
                 comment!("opRETURN");
                 let offset = stack.pop_u256();
                 let size = stack.pop_u256();
                 extend_memory!(offset, size, schedule, memory, gas, error);
-                return ReturnData::new(offset.low_u64() as usize, size.low_u64() as usize, 0)
+                let offset = if size.is_zero() { 0 } else { offset.low_u64() as usize };
+                // RETURN ends its basic block so gas is exact here
+                let gas_left = gas.as_u256().saturating_u64();
+                return ReturnData::new(VmStatus::Success, offset, size.low_u64() as usize, gas_left, refund, logs)
+            }
+            REVERT => {
+                lldb_hook!(pc, gas, stack, lldb_hook_stop);
+                comment!("opREVERT");
+                let offset = stack.pop_u256();
+                let size = stack.pop_u256();
+                extend_memory!(offset, size, schedule, memory, gas, error);
+                let offset = if size.is_zero() { 0 } else { offset.low_u64() as usize };
+                host.revert(checkpoint);
+                let gas_left = gas.as_u256().saturating_u64();
+                return ReturnData::new(VmStatus::Revert, offset, size.low_u64() as usize, gas_left, 0, Vec::new())
+            }
+            SELFDESTRUCT => {
+                comment!("opSELFDESTRUCT");
+                if context.is_static {
+                    error = VmError::WriteProtection;
+                    break;
+                }
+                let beneficiary = stack.pop_u256().to_address();
+                // unlike the other accessing opcodes the cold cost is fully
+                // charged on top of the static fee (EIP-2929)
+                if schedule.access_lists && host.access.access_address(&beneficiary) {
+                    metered_charge!(schedule.cold_account_access_gas, gas, error);
+                }
+                let balance = host.state.balance(&context.address);
+                // EIP-150 introduced the new account cost, EIP-161 only
+                // charges it when value is sent to an empty account
+                let is_new = if schedule.fork >= Fork::Spurious {
+                    !balance.is_zero() & host.state.is_empty(&beneficiary)
+                } else if schedule.fork >= Fork::Tangerine {
+                    !host.state.exists(&beneficiary)
+                } else {
+                    false
+                };
+                if is_new {
+                    metered_charge!(schedule.new_account_gas, gas, error);
+                }
+                if !host.state.is_destructed(&context.address) {
+                    refund += schedule.selfdestruct_refund;
+                }
+                // sending to itself keeps the balance until the account is
+                // destroyed at the end of the transaction
+                transfer(host.state, &context.address, &beneficiary, balance);
+                if !schedule.selfdestruct_created_only || host.state.is_created(&context.address) {
+                    host.state.mark_destructed(&context.address);
+                }
+                let gas_left = gas.as_u256().saturating_u64();
+                return ReturnData::new(VmStatus::Success, 0, 0, gas_left, refund, logs)
             }
             INVALID => {
                 error = VmError::InvalidInstruction;
@@ -1507,9 +2987,11 @@ unsafe fn run_evm(bytecode: &[u8], rom: &VmRom, schedule: &Schedule, gas_limit:
         }
     }
     if let VmError::None = error {
-        return ReturnData::new(0, 0, 0);
+        let gas_left = gas.as_u256().saturating_u64();
+        return ReturnData::new(VmStatus::Success, 0, 0, gas_left, refund, logs);
     }
-    panic!("{:?}", error);
+    host.revert(checkpoint);
+    ReturnData::halt(error)
 }
 
 fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
@@ -1547,36 +3029,51 @@ impl BbInfo {
     }
 }
 
+/// The code, its valid jump destinations and its basic block infos, sized
+/// to the code of each frame
 struct VmRom {
-    data: [u8; VmRom::SIZE]
+    /// Backed by `u64`s to align the jump destinations and the BB infos
+    data: Vec<u64>,
+    code_size: usize
 }
 
 impl VmRom {
-    /// EIP-170 states a max contract code size of 2**14 + 2**13, we round it
-    /// to the next power of two.
-    const MAX_CODESIZE: usize = 32768;
-
-    const JUMPDESTS_SIZE: usize = VmRom::MAX_CODESIZE / 8;
+    /// The code area is a power of two, at least one word of jump
+    /// destinations
+    const MIN_CODESIZE: usize = 64;
 
-    const BB_INFOS_SIZE: usize = VmRom::MAX_CODESIZE * std::mem::size_of::<BbInfo>();
+    /// Zero bytes after the code, a PUSH32 ending the code reads its
+    /// immediate and the STOP after it from the ROM
+    const CODE_PADDING: usize = 33;
 
-    const SIZE: usize = VmRom::MAX_CODESIZE + VmRom::JUMPDESTS_SIZE + VmRom::BB_INFOS_SIZE;
+    fn new() -> VmRom {
+        VmRom { data: Vec::new(), code_size: 0 }
+    }
 
-    const BB_INFOS_OFFSET: usize = VmRom::MAX_CODESIZE + VmRom::JUMPDESTS_SIZE;
+    /// Size of the code area, a power of two
+    fn code_size(&self) -> usize {
+        self.code_size
+    }
 
-    fn new() -> VmRom {
-        VmRom { data: [0; VmRom::SIZE] }
+    fn bb_infos_offset(&self) -> isize {
+        (self.code_size + self.code_size / 8) as isize
     }
 
     fn code(&self) -> *const u8 {
-        self.data.as_ptr()
+        self.data.as_ptr() as *const u8
+    }
+
+    fn code_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.data.as_mut_ptr() as *mut u8, self.code_size)
+        }
     }
 
     fn is_jumpdest(&self, addr: u64) -> bool {
         let jump_dests = unsafe {
-            self.data.as_ptr().offset(VmRom::MAX_CODESIZE as isize) as *mut u64
+            self.code().offset(self.code_size as isize) as *const u64
         };
-        let offset = (addr % (VmRom::MAX_CODESIZE as u64)) as isize;
+        let offset = (addr % (self.code_size as u64)) as isize;
         let bits = unsafe { *jump_dests.offset(offset / 64) };
         let mask = 1u64 << (offset % 64);
         (bits & mask) != 0
@@ -1584,8 +3081,7 @@ impl VmRom {
 
     fn get_bb_info(&self, addr: u64) -> &BbInfo {
         unsafe {
-            let offset = VmRom::BB_INFOS_OFFSET as isize;
-            let bb_infos = self.data.as_ptr().offset(offset) as *mut BbInfo;
+            let bb_infos = self.code().offset(self.bb_infos_offset()) as *const BbInfo;
             &*bb_infos.offset(addr as isize)
         }
     }
@@ -1629,7 +3125,7 @@ impl VmRom {
                 }
             }
         }
-        const OPCODE_INFOS: [(Fee, u16, u16); 256] = [(Zero, 0, 0), (VeryLow, 2, 1), (Low, 2, 1), (VeryLow, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Low, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (VeryLow, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (VeryLow, 2, 1), (VeryLow, 1, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 1, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Base, 0, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Base, 1, 0), (VeryLow, 1, 1), (VeryLow, 2, 0), (VeryLow, 2, 0), (Zero, 0, 0), (Zero, 0, 0), (Mid, 1, 0), (High, 2, 0), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (Jumpdest, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 1, 2), (VeryLow, 2, 3), (VeryLow, 3, 4), (VeryLow, 4, 5), (VeryLow, 5, 6), (VeryLow, 6, 7), (VeryLow, 7, 8), (VeryLow, 8, 9), (VeryLow, 9, 10), (VeryLow, 10, 11), (VeryLow, 11, 12), (VeryLow, 12, 13), (VeryLow, 13, 14), (VeryLow, 14, 15), (VeryLow, 15, 16), (VeryLow, 16, 17), (VeryLow, 2, 2), (VeryLow, 3, 3), (VeryLow, 4, 4), (VeryLow, 5, 5), (VeryLow, 6, 6), (VeryLow, 7, 7), (VeryLow, 8, 8), (VeryLow, 9, 9), (VeryLow, 10, 10), (VeryLow, 11, 11), (VeryLow, 12, 12), (VeryLow, 13, 13), (VeryLow, 14, 14), (VeryLow, 15, 15), (VeryLow, 16, 16), (VeryLow, 17, 17), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 2, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0)];
+        const OPCODE_INFOS: [(Fee, u16, u16); 256] = [(Zero, 0, 0), (VeryLow, 2, 1), (Low, 2, 1), (VeryLow, 2, 1), (Low, 2, 1), (Low, 2, 1), (Low, 2, 1), (Low, 2, 1), (Mid, 3, 1), (Mid, 3, 1), (Exp, 2, 1), (Low, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 1, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 1, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (VeryLow, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (Sha3, 2, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Base, 0, 1), (Balance, 1, 1), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (VeryLow, 1, 1), (Base, 0, 1), (VeryLow, 3, 0), (Base, 0, 1), (VeryLow, 3, 0), (Base, 0, 1), (ExtCode, 1, 1), (ExtCode, 4, 0), (Base, 0, 1), (VeryLow, 3, 0), (ExtCodeHash, 1, 1), (Blockhash, 1, 1), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (Low, 0, 1), (Base, 0, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Base, 1, 0), (VeryLow, 1, 1), (VeryLow, 2, 0), (VeryLow, 2, 0), (Sload, 1, 1), (Zero, 2, 0), (Mid, 1, 0), (High, 2, 0), (Base, 0, 1), (Base, 0, 1), (Base, 0, 1), (Jumpdest, 0, 0), (Transient, 1, 1), (Transient, 2, 0), (VeryLow, 3, 0), (Base, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 0, 1), (VeryLow, 1, 2), (VeryLow, 2, 3), (VeryLow, 3, 4), (VeryLow, 4, 5), (VeryLow, 5, 6), (VeryLow, 6, 7), (VeryLow, 7, 8), (VeryLow, 8, 9), (VeryLow, 9, 10), (VeryLow, 10, 11), (VeryLow, 11, 12), (VeryLow, 12, 13), (VeryLow, 13, 14), (VeryLow, 14, 15), (VeryLow, 15, 16), (VeryLow, 16, 17), (VeryLow, 2, 2), (VeryLow, 3, 3), (VeryLow, 4, 4), (VeryLow, 5, 5), (VeryLow, 6, 6), (VeryLow, 7, 7), (VeryLow, 8, 8), (VeryLow, 9, 9), (VeryLow, 10, 10), (VeryLow, 11, 11), (VeryLow, 12, 12), (VeryLow, 13, 13), (VeryLow, 14, 14), (VeryLow, 15, 15), (VeryLow, 16, 16), (VeryLow, 17, 17), (Log, 2, 0), (Log, 3, 0), (Log, 4, 0), (Log, 5, 0), (Log, 6, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Create, 3, 1), (Call, 7, 1), (Call, 7, 1), (Zero, 2, 0), (Call, 6, 1), (Create, 4, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Zero, 0, 0), (Call, 6, 1), (Zero, 0, 0), (Zero, 0, 0), (Zero, 2, 0), (Zero, 0, 0), (Selfdestruct, 1, 0)];
         let mut addr: u32 = 0;
         let mut stack_size: u16 = 0;
         let mut stack_min_size: u16 = 0;
@@ -1640,10 +3136,8 @@ impl VmRom {
         let mut i: usize = 0;
         while i < bytecode.len() {
             let code = bytecode[i];
-            let opcode = unsafe {
-                std::mem::transmute::<u8, EvmOpcode>(code)
-            };
-            let (fee, delta, alpha) = OPCODE_INFOS[code as usize];
+            let opcode = EvmOpcode::decode(code, schedule.fork);
+            let (fee, delta, alpha) = OPCODE_INFOS[opcode as usize];
             // new_stack_size is (stack_size + needed + alpha) - delta
             // and represents the new stack size after the opcode has been
             // dispatched
@@ -1677,9 +3171,7 @@ impl VmRom {
             }
             else {
                 let code = bytecode[i];
-                let opcode = unsafe {
-                    std::mem::transmute::<u8, EvmOpcode>(code)
-                };
+                let opcode = EvmOpcode::decode(code, schedule.fork);
                 if opcode == EvmOpcode::JUMPDEST {
                     block_infos.push(BlockInfo::partial(
                         addr, stack_min_size, stack_max_size, stack_size, gas)
@@ -1694,8 +3186,8 @@ impl VmRom {
         }
         // backward pass, write BB infos to rom
         let bb_infos = unsafe {
-            let offset = VmRom::BB_INFOS_OFFSET as isize;
-            self.data.as_ptr().offset(offset) as *mut BbInfo
+            let offset = self.bb_infos_offset();
+            self.data.as_mut_ptr().offset(offset / 8) as *mut BbInfo
         };
         for info in block_infos.iter().rev() {
             if info.is_basic_block {
@@ -1725,13 +3217,12 @@ impl VmRom {
     }
 
     fn init(&mut self, bytecode: &[u8], schedule: &Schedule) {
-        // erase rom
-        for b in &mut self.data[..] {
-            *b = 0;
-        }
-        if bytecode.len() > VmRom::MAX_CODESIZE {
-            panic!("bytecode is too big ({:?} bytes)", bytecode.len());
-        }
+        // allocate a zeroed rom for the code
+        let code_size = std::cmp::max(bytecode.len() + VmRom::CODE_PADDING, VmRom::MIN_CODESIZE)
+            .next_power_of_two();
+        let size = code_size + code_size / 8 + code_size * std::mem::size_of::<BbInfo>();
+        self.data = vec![0; size / 8];
+        self.code_size = code_size;
         // copy bytecode
         #[cfg(target_endian = "little")]
         {
@@ -1739,16 +3230,19 @@ impl VmRom {
             let mut i: usize = 0;
             while i < bytecode.len() {
                 let code = bytecode[i];
-                let opcode = unsafe {
-                    std::mem::transmute::<u8, EvmOpcode>(code)
-                };
-                self.data[i] = opcode.to_internal() as u8;
+                let opcode = EvmOpcode::decode(code, schedule.fork);
+                let code = self.code_mut();
+                code[i] = opcode.to_internal() as u8;
                 if opcode.is_push() {
                     let num_bytes = opcode.push_index() + 1;
                     let start = i + 1;
                     let end = start + num_bytes;
-                    let dest = &mut self.data[start..end];
-                    VmRom::swap_bytes(&bytecode[start..end], dest);
+                    let dest = &mut code[start..end];
+                    // a PUSH cut by the end of the code reads zeros
+                    let mut immediate: [u8; 32] = [0; 32];
+                    let available = &bytecode[start.min(bytecode.len())..end.min(bytecode.len())];
+                    immediate[..available.len()].copy_from_slice(available);
+                    VmRom::swap_bytes(&immediate[..num_bytes], dest);
                     i += 1 + num_bytes;
                 }
                 else {
@@ -1761,9 +3255,9 @@ impl VmRom {
             unimplemented!();
         }
         // write valid jump destinations
-        let jump_dests_offset = VmRom::MAX_CODESIZE as isize;
+        let jump_dests_offset = (self.code_size / 8) as isize;
         let jump_dests = unsafe {
-            self.data.as_mut_ptr().offset(jump_dests_offset) as *mut u64
+            self.data.as_mut_ptr().offset(jump_dests_offset)
         };
         let mut bits: u64 = 0;
         let mut i: usize = 0;
@@ -1771,9 +3265,7 @@ impl VmRom {
             // save i for later in j
             let j = i;
             let code = bytecode[i];
-            let opcode = unsafe {
-                std::mem::transmute::<u8, EvmOpcode>(code)
-            };
+            let opcode = EvmOpcode::decode(code, schedule.fork);
             if opcode.is_push() {
                 let num_bytes = opcode.push_index() + 1;
                 i += 1 + num_bytes;
@@ -1834,6 +3326,9 @@ fn print_config() {
 
 const VM_DEFAULT_GAS: u64 = 20_000_000_000_000;
 
+/// Maximum number of nested frames below the transaction
+const VM_MAX_DEPTH: usize = 1024;
+
 struct Bytecode<'a> {
     data: &'a [u8],
     addr: usize
@@ -1931,30 +3426,148 @@ fn disasm(input: &str) {
     }
 }
 
-fn evm(input: &str, gas_limit: U256) {
-    let temp = decode_hex(input);
+/// Block and fork rules the transaction executes with
+struct ExecutionEnv<'a> {
+    pub env: BlockEnv,
+    pub hashes: &'a dyn BlockHashes,
+    pub fork: Fork,
+}
+
+/// Accounts, storage and warm entries before the transaction
+struct PreState {
+    pub state: MemoryState,
+    pub storage: MemoryStorage,
+    pub access: AccessSet,
+}
+
+fn evm(code: &str, context: VmContext, exec_env: ExecutionEnv, pre_state: PreState, gas_limit: U256) {
+    let ExecutionEnv { env, hashes, fork } = exec_env;
+    let PreState { mut state, mut storage, mut access } = pre_state;
+    let temp = decode_hex(code);
     match temp {
         Ok(bytes) => {
             //println!("{} bytes", bytes.len());
-            let schedule = Schedule::default();
+            let schedule = Schedule::from_fork(fork);
             let mut rom = VmRom::new();
             rom.init(&bytes, &schedule);
+            // the sender and the recipient of the transaction start warm
+            access.insert_address(context.origin);
+            access.insert_address(context.address);
+            // and so does the coinbase since Shanghai (EIP-3651)
+            if fork >= Fork::Shanghai {
+                access.insert_address(env.coinbase);
+            }
+            // precompiled contracts are always warm
+            for address in precompiles::addresses(fork) {
+                access.insert_address(address);
+            }
             let mut memory = VmMemory::new();
-            memory.init(gas_limit);
+            memory.init(gas_limit, &schedule);
+            let (balance, nonce) = match state.account(&context.address) {
+                Some(account) => (account.balance, account.nonce),
+                None => (U256::default(), 0)
+            };
+            state.insert(context.address, Account::new(balance, nonce, bytes.clone()));
+            let mut host = VmHost {
+                env: &env,
+                hashes,
+                schedule: &schedule,
+                state: &mut state,
+                storage: &mut storage,
+                // transient storage only lives for the transaction
+                transient: TransientStorage::new(),
+                access,
+            };
+            let ret_data = unsafe {
+                run_evm(&bytes, &context, &rom, &mut host, gas_limit, &mut memory)
+            };
+            for address in state.apply_destructs() {
+                storage.clear(&address);
+            }
             let slice = unsafe {
-                let ret_data = run_evm(&bytes, &rom, &schedule, gas_limit, &mut memory);
                 memory.slice(ret_data.offset as isize, ret_data.size)
             };
-            let mut buffer = String::with_capacity(512);
-            for byte in slice {
-                let _ = write!(buffer, "{:02x}", byte);
+            match ret_data.status {
+                VmStatus::Success => {
+                    println!("0x{}", encode_hex(slice));
+                    print_logs(&ret_data.logs);
+                },
+                VmStatus::Revert => {
+                    println!("reverted 0x{}", encode_hex(slice));
+                    if let Some(reason) = revert_reason(slice) {
+                        println!("reason: {:?}", reason);
+                    }
+                },
+                VmStatus::Halt(error) => println!("halted: {:?}", error)
             }
-            println!("0x{:}", buffer);
         },
         Err(e) => println!("{:?}", e)
     };
 }
 
+/// Decodes the message of a Solidity `Error(string)` revert
+fn revert_reason(data: &[u8]) -> Option<String> {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+    if data.len() < 4 || data[0..4] != ERROR_SELECTOR {
+        return None;
+    }
+    let args = &data[4..];
+    let word = |offset: usize| -> Option<usize> {
+        let bytes = args.get(offset..offset.checked_add(32)?)?;
+        let value = U256::from_be_bytes(bytes);
+        if value.le_u64() { usize::try_from(value.low_u64()).ok() } else { None }
+    };
+    let offset = word(0)?;
+    let len = word(offset)?;
+    let start = offset.checked_add(32)?;
+    let message = args.get(start..start.checked_add(len)?)?;
+    String::from_utf8(message.to_vec()).ok()
+}
+
+fn print_logs(logs: &[Log]) {
+    for log in logs {
+        let topics: Vec<String> = log.topics.iter()
+            .map(|topic| format!("0x{}", encode_hex(&topic.to_be_bytes())))
+            .collect();
+        println!("log 0x{} [{}] 0x{}", encode_hex(log.address.as_bytes()), topics.join(", "), encode_hex(&log.data));
+    }
+}
+
+fn u256_arg(matches: &ArgMatches, name: &str, flag: &str, default: U256) -> U256 {
+    if let Some(value) = matches.value_of(name) {
+        match ethereum_types::U256::from_dec_str(value) {
+            Ok(temp) => {
+                let mask = ethereum_types::U256::from(u64::MAX);
+                let data: [u64; 4] = [
+                    ((temp >>   0) & mask).as_u64(),
+                    ((temp >>  64) & mask).as_u64(),
+                    ((temp >> 128) & mask).as_u64(),
+                    ((temp >> 192) & mask).as_u64()
+                ];
+                return U256::from_slice(&data);
+            }
+            Err(err) => {
+                println!("Invalid {}: {:?}", flag, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    default
+}
+
+fn address_arg(matches: &ArgMatches, name: &str, flag: &str) -> Address {
+    if let Some(value) = matches.value_of(name) {
+        match value.parse::<Address>() {
+            Ok(address) => return address,
+            Err(err) => {
+                println!("Invalid {}: {:?}", flag, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    Address::zero()
+}
+
 fn main() {
     let matches =
         App::new("Psyche")
@@ -1968,7 +3581,56 @@ fn main() {
                     .takes_value(true)
                     .short("g")
                     .long("gas")
-                    .help("Supplied gas as decimal")))
+                    .help("Supplied gas as decimal"))
+                .arg(Arg::with_name("INPUT")
+                    .takes_value(true)
+                    .short("i")
+                    .long("input")
+                    .help("Call data as hex (without 0x)"))
+                .arg(Arg::with_name("ADDRESS")
+                    .takes_value(true)
+                    .long("address")
+                    .help("Address of the executing account as hex (without 0x)"))
+                .arg(Arg::with_name("CALLER")
+                    .takes_value(true)
+                    .long("caller")
+                    .help("Caller address as hex (without 0x)"))
+                .arg(Arg::with_name("ORIGIN")
+                    .takes_value(true)
+                    .long("origin")
+                    .help("Transaction origin address as hex (without 0x)"))
+                .arg(Arg::with_name("VALUE")
+                    .takes_value(true)
+                    .long("value")
+                    .help("Transferred value in wei as decimal"))
+                .arg(Arg::with_name("GAS_PRICE")
+                    .takes_value(true)
+                    .long("gas-price")
+                    .help("Gas price in wei as decimal"))
+                .arg(Arg::with_name("ENV")
+                    .takes_value(true)
+                    .long("env")
+                    .help("Block environment as JSON file"))
+                .arg(Arg::with_name("BLOCK_HASHES")
+                    .takes_value(true)
+                    .long("block-hashes")
+                    .help("Hashes of past blocks as JSON file mapping numbers to hashes"))
+                .arg(Arg::with_name("STATE")
+                    .takes_value(true)
+                    .long("state")
+                    .help("Initial accounts as JSON file in the state tests `pre` format"))
+                .arg(Arg::with_name("STORAGE")
+                    .takes_value(true)
+                    .long("storage")
+                    .help("Initial storage of the executing account as JSON file mapping slots to values, replaces its storage from --state"))
+                .arg(Arg::with_name("ACCESS_LIST")
+                    .takes_value(true)
+                    .long("access-list")
+                    .help("Access list of the transaction as JSON file (EIP-2930)"))
+                .arg(Arg::with_name("FORK")
+                    .takes_value(true)
+                    .long("fork")
+                    .help("Fork rules to apply (e.g. istanbul), defaults to the latest")))
             .subcommand(SubCommand::with_name("disasm")
                 .about("Disassemble EVM bytecode")
                 .arg(Arg::with_name("CODE")
@@ -1983,24 +3645,617 @@ fn main() {
         return;
     }
     if let Some(matches) = matches.subcommand_matches("evm") {
-        let mut gas = U256::from_u64(VM_DEFAULT_GAS);
-        if let Some(value) = matches.value_of("GAS") {
-            match ethereum_types::U256::from_dec_str(value) {
-                Ok(temp) => {
-                    let mask = ethereum_types::U256::from(u64::max_value());
-                    let data: [u64; 4] = [
-                        ((temp >>   0) & mask).as_u64(),
-                        ((temp >>  64) & mask).as_u64(),
-                        ((temp >> 128) & mask).as_u64(),
-                        ((temp >> 192) & mask).as_u64()
-                    ];
-                    gas = U256::from_slice(&data);
+        let gas = u256_arg(matches, "GAS", "--gas", U256::from_u64(VM_DEFAULT_GAS));
+        let input = match decode_hex(matches.value_of("INPUT").unwrap_or("")) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Invalid --input: {:?}", err);
+                std::process::exit(1);
+            }
+        };
+        let context = VmContext {
+            address: address_arg(matches, "ADDRESS", "--address"),
+            caller: address_arg(matches, "CALLER", "--caller"),
+            origin: address_arg(matches, "ORIGIN", "--origin"),
+            value: u256_arg(matches, "VALUE", "--value", U256::default()),
+            gas_price: u256_arg(matches, "GAS_PRICE", "--gas-price", U256::default()),
+            input,
+            is_static: false,
+            depth: 0,
+        };
+        let env = match matches.value_of("ENV") {
+            Some(path) => {
+                let temp = std::fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| BlockEnv::from_json(&json));
+                match temp {
+                    Ok(env) => env,
+                    Err(err) => {
+                        println!("Invalid --env: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            },
+            None => BlockEnv::default()
+        };
+        let hashes: Box<dyn BlockHashes> = match matches.value_of("BLOCK_HASHES") {
+            Some(path) => {
+                let temp = std::fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| MemoryBlockHashes::from_json(&json));
+                match temp {
+                    Ok(hashes) => Box::new(hashes),
+                    Err(err) => {
+                        println!("Invalid --block-hashes: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            },
+            None => Box::new(SyntheticBlockHashes)
+        };
+        let (state, mut storage) = match matches.value_of("STATE") {
+            Some(path) => {
+                let temp = std::fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| parse_alloc(&json));
+                match temp {
+                    Ok(alloc) => alloc,
+                    Err(err) => {
+                        println!("Invalid --state: {}", err);
+                        std::process::exit(1);
+                    }
                 }
-                Err(err) => println!("Invalid --gas: {:?}", err)
+            },
+            None => (MemoryState::new(), MemoryStorage::new())
+        };
+        if let Some(path) = matches.value_of("STORAGE") {
+            // the file holds the whole storage of the account, as before --state
+            storage.clear(&context.address);
+            let temp = std::fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+                .and_then(|json| storage.insert_json(context.address, &json));
+            if let Err(err) = temp {
+                println!("Invalid --storage: {}", err);
+                std::process::exit(1);
             }
         }
+        let access = match matches.value_of("ACCESS_LIST") {
+            Some(path) => {
+                let temp = std::fs::read_to_string(path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| AccessSet::from_json(&json));
+                match temp {
+                    Ok(access) => access,
+                    Err(err) => {
+                        println!("Invalid --access-list: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            },
+            None => AccessSet::new()
+        };
+        let fork = match matches.value_of("FORK").map(str::parse::<Fork>) {
+            Some(Ok(fork)) => fork,
+            Some(Err(err)) => {
+                println!("Invalid --fork: {}", err);
+                std::process::exit(1);
+            },
+            None => Fork::default()
+        };
+        if fork > Fork::latest() {
+            eprintln!("Warning: {:?} is not fully supported, it runs with the {:?} rules", fork, Fork::latest());
+        }
         let code = matches.value_of("CODE").unwrap();
-        evm(code, gas);
+        let exec_env = ExecutionEnv { env, hashes: hashes.as_ref(), fork };
+        let pre_state = PreState { state, storage, access };
+        evm(code, context, exec_env, pre_state, gas);
         return;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a hex string of up to 64 digits
+    fn u256(hex: &str) -> U256 {
+        let hex = format!("{:0>64}", hex.trim_start_matches("0x"));
+        U256::from_be_bytes(&decode_hex(&hex).unwrap())
+    }
+
+    fn neg(value: u64) -> U256 {
+        neg_u256(U256::from_u64(value))
+    }
+
+    #[test]
+    fn division_by_zero_is_zero() {
+        let x = u256("0x1234567890abcdef1234567890abcdef1234567890abcdef");
+        let zero = U256::default();
+        assert_eq!(div_u256(x, zero), zero);
+        assert_eq!(mod_u256(x, zero), zero);
+        assert_eq!(sdiv_u256(x, zero), zero);
+        assert_eq!(smod_u256(x, zero), zero);
+        assert_eq!(sdiv_u256(neg(1), zero), zero);
+    }
+
+    #[test]
+    fn sdiv_min_by_minus_one_wraps() {
+        let min = u256("0x8000000000000000000000000000000000000000000000000000000000000000");
+        assert_eq!(sdiv_u256(min, neg(1)), min);
+        assert_eq!(smod_u256(min, neg(1)), U256::default());
+    }
+
+    #[test]
+    fn signed_division_truncates_toward_zero() {
+        assert_eq!(sdiv_u256(neg(7), U256::from_u64(2)), neg(3));
+        assert_eq!(sdiv_u256(U256::from_u64(7), neg(2)), neg(3));
+        assert_eq!(sdiv_u256(neg(7), neg(2)), U256::from_u64(3));
+    }
+
+    #[test]
+    fn smod_takes_the_sign_of_the_dividend() {
+        assert_eq!(smod_u256(neg(7), U256::from_u64(3)), neg(1));
+        assert_eq!(smod_u256(U256::from_u64(7), neg(3)), U256::from_u64(1));
+        assert_eq!(smod_u256(neg(7), neg(3)), neg(1));
+        assert_eq!(smod_u256(neg(6), U256::from_u64(3)), U256::default());
+    }
+
+    #[test]
+    fn divrem_single_limb_divisor() {
+        let a = u256("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        let (q, r) = divrem_u256(a, U256::from_u64(10));
+        assert_eq!(q, u256("0x1999999999999999999999999999999999999999999999999999999999999999"));
+        assert_eq!(r, U256::from_u64(5));
+    }
+
+    #[test]
+    fn divrem_two_limb_divisor_corrects_qhat() {
+        let a = u256("0xffffffffffffffff000000010000000000000000000000018000000000000000");
+        let b = u256("0x7fffffffffffffffffffffffffffffff");
+        let (q, r) = divrem_u256(a, b);
+        assert_eq!(q, u256("0x1fffffffffffffffe0000000200000003"));
+        assert_eq!(r, u256("0x7fffffffffffffff8000000200000003"));
+    }
+
+    #[test]
+    fn divrem_three_limb_divisor_adds_back() {
+        let a = u256("0xfffffffffffffffefffffffffffffffe00000001000000000000000100000000");
+        let b = u256("0xfffffffffffffffffffffffffffffffefffffffffffffffe");
+        let (q, r) = divrem_u256(a, b);
+        assert_eq!(q, u256("0xfffffffffffffffe"));
+        assert_eq!(r, u256("0xffffffffffffffff000000010000000000000000fffffffc"));
+        let a = u256("0x7ffffffffffffffffffffffffffffffe00000000000000010000000100000000");
+        let b = u256("0x7ffffffffffffffffffffffffffffffe7fffffffffffffff");
+        let (q, r) = divrem_u256(a, b);
+        assert_eq!(q, u256("0xffffffffffffffff"));
+        assert_eq!(r, u256("0x7fffffffffffffff800000000000000080000000ffffffff"));
+    }
+
+    #[test]
+    fn divrem_divisor_with_top_bit_set() {
+        let a = u256("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        let b = u256("0x8000000000000000000000000000000000000000000000000000000000000001");
+        let (q, r) = divrem_u256(a, b);
+        assert_eq!(q, U256::from_u64(1));
+        assert_eq!(r, u256("0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe"));
+        // a smaller dividend leaves it untouched
+        let (q, r) = divrem_u256(U256::from_u64(5), b);
+        assert_eq!(q, U256::default());
+        assert_eq!(r, U256::from_u64(5));
+    }
+
+    #[test]
+    fn divrem_limbs_matches_divrem_u256() {
+        let a = u256("0x7fffffffffffffff000000000000000000000000000000000000000000000001");
+        let b = u256("0xfffffffffffffffe80000000000000008000000000000000");
+        let mut q = U256::default();
+        let mut r = U256::default();
+        divrem_limbs(&a.0, &b.0, &mut q.0, &mut r.0);
+        assert_eq!(q, u256("0x7fffffffffffffff"));
+        assert_eq!(r, u256("0xbffffffffffffffe40000000000000008000000000000001"));
+        assert_eq!(divrem_u256(a, b), (q, r));
+    }
+
+    #[test]
+    fn divrem_limbs_of_any_length() {
+        // xorshift, with runs of ones and zeros to hit the qhat corrections
+        let mut seed: u64 = 0x9e3779b97f4a7c15;
+        let mut limb = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            match seed % 4 {
+                0 => u64::MAX,
+                1 => 0,
+                _ => seed
+            }
+        };
+        for m in 1..=12 {
+            for n in 1..=m + 1 {
+                let a: Vec<u64> = (0..m).map(|_| limb()).collect();
+                let mut b: Vec<u64> = (0..n).map(|_| limb()).collect();
+                b[n-1] |= 1;
+                let mut q = vec![0u64; m];
+                let mut r = vec![0u64; n];
+                divrem_limbs(&a, &b, &mut q, &mut r);
+                // a = q * b + r with r < b
+                let mut c = vec![0u64; m + n];
+                mul_limbs(&q, &b, &mut c);
+                let mut carry = 0u128;
+                for i in 0..m + n {
+                    let t = (c[i] as u128) + (*r.get(i).unwrap_or(&0) as u128) + carry;
+                    c[i] = t as u64;
+                    carry = t >> 64;
+                }
+                assert_eq!(&c[..m], &a[..]);
+                assert!(c[m..].iter().all(|x| *x == 0));
+                assert_eq!(r.iter().rev().cmp(b.iter().rev()), std::cmp::Ordering::Less);
+            }
+        }
+    }
+
+    const MAX: &str = "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    #[test]
+    fn full_mul_keeps_the_high_limbs() {
+        let max = u256(MAX);
+        let product = full_mul_u256(max, max);
+        // (2^256 - 1)^2 = 2^512 - 2^257 + 1
+        let m = u64::MAX;
+        assert_eq!(product, [1, 0, 0, 0, m - 1, m, m, m]);
+    }
+
+    #[test]
+    fn mulmod_reduces_the_full_product() {
+        let max = u256(MAX);
+        assert_eq!(mulmod_u256(max, max, max), U256::default());
+        assert_eq!(mulmod_u256(max, max, U256::from_u64(12)), U256::from_u64(9));
+        assert_eq!(mulmod_u256(max, max, u256("0x100000000000000000000000000000003")), U256::from_u64(0x40));
+        let a = u256("0x100000000000000000000000000000000000000000000000000");
+        let b = u256("0x10000000000000000000000000");
+        let n = u256("0xffffffffffffffffffffffffffffffffffffffffffffffff");
+        assert_eq!(mulmod_u256(a, b, n), u256("0x1000000000000000000000000000"));
+    }
+
+    #[test]
+    fn addmod_keeps_the_carry() {
+        let max = u256(MAX);
+        assert_eq!(addmod_u256(max, U256::from_u64(2), U256::from_u64(10)), U256::from_u64(7));
+        assert_eq!(addmod_u256(max, max, max), U256::default());
+        let n = u256("0x8000000000000000000000000000000000000000000000000000000000000007");
+        assert_eq!(addmod_u256(max, max, n), u256("0x7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe9"));
+    }
+
+    #[test]
+    fn modulo_zero_is_zero() {
+        let max = u256(MAX);
+        let zero = U256::default();
+        assert_eq!(addmod_u256(max, max, zero), zero);
+        assert_eq!(mulmod_u256(max, max, zero), zero);
+        assert_eq!(mulmod_u256(U256::from_u64(3), U256::from_u64(5), zero), zero);
+    }
+
+    #[test]
+    fn signed_comparisons() {
+        unsafe {
+            let one = U256::from_u64(1);
+            assert!(lt_u256(one, u256(MAX)));
+            assert!(!slt_u256(one, u256(MAX)));
+            assert!(sgt_u256(one, u256(MAX)));
+            assert!(slt_u256(neg(2), neg(1)));
+            assert!(!sgt_u256(neg(1), neg(1)));
+            let min = u256("0x8000000000000000000000000000000000000000000000000000000000000000");
+            let max = u256("0x7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+            assert!(slt_u256(min, max));
+            assert!(sgt_u256(max, min));
+            assert!(gt_u256(min, max));
+        }
+    }
+
+    #[test]
+    fn shifts_by_large_counts() {
+        unsafe {
+            let one = U256::from_u64(1);
+            let min = u256("0x8000000000000000000000000000000000000000000000000000000000000000");
+            assert_eq!(shl_u256(U256::from_u64(255), one), min);
+            assert_eq!(shl_u256(U256::from_u64(256), one), U256::default());
+            assert_eq!(shr_u256(U256::from_u64(255), min), one);
+            assert_eq!(shr_u256(U256::from_u64(256), min), U256::default());
+            assert_eq!(shr_u256(u256(MAX), u256(MAX)), U256::default());
+            assert_eq!(shr_u256(U256::from_u64(68), min),
+                u256("0x0000000000000000080000000000000000000000000000000000000000000000"));
+            // SAR fills with the sign bit
+            assert_eq!(sar_u256(U256::from_u64(255), min), u256(MAX));
+            assert_eq!(sar_u256(U256::from_u64(256), min), u256(MAX));
+            assert_eq!(sar_u256(u256(MAX), neg(16)), u256(MAX));
+            assert_eq!(sar_u256(U256::from_u64(2), neg(16)), neg(4));
+            assert_eq!(sar_u256(U256::from_u64(256), shr_u256(one, min)), U256::default());
+            assert_eq!(sar_u256(U256::from_u64(1), U256::from_u64(7)), U256::from_u64(3));
+        }
+    }
+
+    fn signextend(a: U256, b: U256) -> U256 {
+        // the interpreter passes the sign byte read from the stack slot of `b`
+        let byte = b.to_be_bytes()[31 - (a.low_u64() % 32) as usize];
+        unsafe { signextend_u256(a, b, byte as i64) }
+    }
+
+    #[test]
+    fn signextend_edge_cases() {
+        assert_eq!(signextend(U256::default(), U256::from_u64(0xff)), u256(MAX));
+        assert_eq!(signextend(U256::default(), U256::from_u64(0x7f)), U256::from_u64(0x7f));
+        assert_eq!(signextend(U256::from_u64(1), U256::from_u64(0x12_8000)), neg(0x8000));
+        assert_eq!(signextend(U256::from_u64(30), u256(MAX)), u256(MAX));
+        assert_eq!(signextend(U256::from_u64(31), U256::from_u64(0xff)), U256::from_u64(0xff));
+        assert_eq!(signextend(U256::from_u64(32), U256::from_u64(0xff)), U256::from_u64(0xff));
+        assert_eq!(signextend(u256(MAX), U256::from_u64(0xff)), U256::from_u64(0xff));
+    }
+
+    #[test]
+    fn parse_u256_hex_strings() {
+        let parse = |text: &str| block::parse_u256(&serde_json::Value::String(text.to_string()));
+        assert_eq!(parse("0x2a"), Some(U256::from_u64(42)));
+        assert_eq!(parse("0x0"), Some(U256::default()));
+        assert_eq!(parse("0x000"), Some(U256::default()));
+        assert_eq!(parse("42"), Some(U256::from_u64(42)));
+        assert_eq!(parse("0x"), None);
+        assert_eq!(parse("0xzz"), None);
+    }
+
+    /// Runs `code` as the transaction code of account 0xaa and returns the
+    /// outcome, whose `gas` is the gas left
+    fn execute(code: &str, fork: Fork, gas: u64, storage: MemoryStorage, access: AccessSet) -> ReturnData {
+        execute_in(MemoryState::new(), code, fork, gas, storage, access).0
+    }
+
+    /// Same as `execute` with the other accounts of `state`, also returns the
+    /// output
+    fn execute_in(state: MemoryState, code: &str, fork: Fork, gas: u64, storage: MemoryStorage, access: AccessSet) -> (ReturnData, Vec<u8>) {
+        let bytes = decode_hex(code).unwrap();
+        let schedule = Schedule::from_fork(fork);
+        let env = BlockEnv::default();
+        let context = VmContext {
+            address: Address::from_low_u64_be(0xaa),
+            caller: Address::from_low_u64_be(0xcc),
+            origin: Address::from_low_u64_be(0xcc),
+            value: U256::default(),
+            gas_price: U256::default(),
+            input: Vec::new(),
+            is_static: false,
+            depth: 0,
+        };
+        let mut state = state;
+        state.insert(context.address, Account::new(U256::default(), 0, bytes.clone()));
+        let mut storage = storage;
+        let mut host = VmHost {
+            env: &env,
+            hashes: &SyntheticBlockHashes,
+            schedule: &schedule,
+            state: &mut state,
+            storage: &mut storage,
+            transient: TransientStorage::new(),
+            access,
+        };
+        unsafe { run_frame(&bytes, &context, &mut host, gas) }
+    }
+
+    fn gas_used(code: &str, fork: Fork) -> u64 {
+        let gas = 1_000_000;
+        let result = execute(code, fork, gas, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Success));
+        gas - result.gas
+    }
+
+    /// Runs SSTOREs to slot 0 holding `original`, returns the gas used and the refund
+    fn sstore_case(fork: Fork, code: &str, original: u64, warm: bool) -> (u64, i64) {
+        let gas = 100_000;
+        let address = Address::from_low_u64_be(0xaa);
+        let mut storage = MemoryStorage::new();
+        storage.insert(address, U256::default(), U256::from_u64(original));
+        let mut access = AccessSet::new();
+        if warm {
+            access.insert_slot(address, U256::default());
+        }
+        let result = execute(code, fork, gas, storage, access);
+        assert!(matches!(result.status, VmStatus::Success));
+        (gas - result.gas, result.refund)
+    }
+
+    /// Code, original value and (gas, refund) in Constantinople, Istanbul,
+    /// Berlin and London, Berlin and London run with a warm slot
+    type SstoreCase = (&'static str, u64, [(u64, i64); 4]);
+
+    /// Test cases of EIP-1283, EIP-2200 and EIP-3529
+    const SSTORE_CASES: [SstoreCase; 17] = [
+        ("60006000556000600055", 0, [(412, 0), (1612, 0), (212, 0), (212, 0)]),
+        ("60006000556001600055", 0, [(20212, 0), (20812, 0), (20112, 0), (20112, 0)]),
+        ("60016000556000600055", 0, [(20212, 19800), (20812, 19200), (20112, 19900), (20112, 19900)]),
+        ("60016000556002600055", 0, [(20212, 0), (20812, 0), (20112, 0), (20112, 0)]),
+        ("60016000556001600055", 0, [(20212, 0), (20812, 0), (20112, 0), (20112, 0)]),
+        ("60006000556000600055", 1, [(5212, 15000), (5812, 15000), (3012, 15000), (3012, 4800)]),
+        ("60006000556001600055", 1, [(5212, 4800), (5812, 4200), (3012, 2800), (3012, 2800)]),
+        ("60006000556002600055", 1, [(5212, 0), (5812, 0), (3012, 0), (3012, 0)]),
+        ("60026000556000600055", 1, [(5212, 15000), (5812, 15000), (3012, 15000), (3012, 4800)]),
+        ("60026000556003600055", 1, [(5212, 0), (5812, 0), (3012, 0), (3012, 0)]),
+        ("60026000556001600055", 1, [(5212, 4800), (5812, 4200), (3012, 2800), (3012, 2800)]),
+        ("60026000556002600055", 1, [(5212, 0), (5812, 0), (3012, 0), (3012, 0)]),
+        ("60016000556000600055", 1, [(5212, 15000), (5812, 15000), (3012, 15000), (3012, 4800)]),
+        ("60016000556002600055", 1, [(5212, 0), (5812, 0), (3012, 0), (3012, 0)]),
+        ("60016000556001600055", 1, [(412, 0), (1612, 0), (212, 0), (212, 0)]),
+        ("600160005560006000556001600055", 0, [(40218, 19800), (40818, 19200), (40118, 19900), (40118, 19900)]),
+        ("600060005560016000556000600055", 1, [(10218, 19800), (10818, 19200), (5918, 17800), (5918, 7600)]),
+    ];
+
+    #[test]
+    fn sstore_net_gas_metering() {
+        let forks = [Fork::Constantinople, Fork::Istanbul, Fork::Berlin, Fork::London];
+        for (code, original, expected) in SSTORE_CASES.iter() {
+            for (fork, expected) in forks.iter().zip(expected.iter()) {
+                let warm = *fork >= Fork::Berlin;
+                assert_eq!(sstore_case(*fork, code, *original, warm), *expected, "{:?} {} {}", fork, code, original);
+            }
+        }
+    }
+
+    #[test]
+    fn sstore_legacy_and_cold_metering() {
+        // before Constantinople every store is a set or a reset
+        assert_eq!(sstore_case(Fork::Byzantium, "60016000556000600055", 0, false), (25012, 15000));
+        assert_eq!(sstore_case(Fork::Byzantium, "60006000556000600055", 0, false), (10012, 0));
+        // the first access to a cold slot adds the cold surcharge once
+        assert_eq!(sstore_case(Fork::Berlin, "60006000556000600055", 0, false), (2312, 0));
+        assert_eq!(sstore_case(Fork::London, "60006000556001600055", 1, false), (5112, 2800));
+    }
+
+    #[test]
+    fn sstore_sentry_halts() {
+        // PUSH1 0 PUSH1 0 SSTORE leaves 2300 gas at the SSTORE
+        let result = execute("6000600055", Fork::Istanbul, 2306, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Halt(VmError::OutOfGas)));
+        let result = execute("6000600055", Fork::Istanbul, 2307, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Success));
+        assert_eq!(result.gas, 2307 - 6 - 800);
+        // Constantinople has no sentry
+        let result = execute("6000600055", Fork::Constantinople, 206, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Success));
+    }
+
+    #[test]
+    fn account_access_pricing() {
+        // PUSH1 0xdd BALANCE PUSH1 0xdd BALANCE STOP
+        assert_eq!(gas_used("60dd3160dd3100", Fork::Frontier), 3 + 20 + 3 + 20);
+        assert_eq!(gas_used("60dd3160dd3100", Fork::Tangerine), 3 + 400 + 3 + 400);
+        assert_eq!(gas_used("60dd3160dd3100", Fork::Istanbul), 3 + 700 + 3 + 700);
+        assert_eq!(gas_used("60dd3160dd3100", Fork::Berlin), 3 + 2600 + 3 + 100);
+        // PUSH1 1 SLOAD PUSH1 1 SLOAD STOP
+        assert_eq!(gas_used("60015460015400", Fork::Frontier), 3 + 50 + 3 + 50);
+        assert_eq!(gas_used("60015460015400", Fork::Istanbul), 3 + 800 + 3 + 800);
+        assert_eq!(gas_used("60015460015400", Fork::Berlin), 3 + 2100 + 3 + 100);
+        // entries of the access list start warm
+        let mut access = AccessSet::new();
+        access.insert_address(Address::from_low_u64_be(0xdd));
+        let result = execute("60dd3160dd3100", Fork::Berlin, 10_000, MemoryStorage::new(), access);
+        assert_eq!(10_000 - result.gas, 3 + 100 + 3 + 100);
+    }
+
+    #[test]
+    fn failed_frames_revert_warm_accounts() {
+        // CALL(gas, 0xbb, 0, 0, 0, 0, 0) POP PUSH2 0xdd BALANCE POP STOP
+        let code = "60008080808060bb5af1506100dd315000";
+        let run = |callee: &str| {
+            let mut state = MemoryState::new();
+            state.insert(Address::from_low_u64_be(0xbb), Account::new(U256::default(), 0, decode_hex(callee).unwrap()));
+            let (result, _) = execute_in(state, code, Fork::Berlin, 100_000, MemoryStorage::new(), AccessSet::new());
+            assert!(matches!(result.status, VmStatus::Success));
+            100_000 - result.gas
+        };
+        // the callee reads the balance of 0xdd then stops or reverts
+        let kept = run("6100dd315000");
+        let reverted = run("6100dd315060006000fd");
+        assert_eq!(reverted - kept, 3 + 3 + (2600 - 100));
+    }
+
+    #[test]
+    fn nested_calls_stop_at_max_depth() {
+        // counts the frames in slot 0 and calls itself, then returns slot 0
+        let code = "600054600101600055600080808080305af15060005460005260206000f3";
+        let (result, output) = execute_in(MemoryState::new(), code, Fork::Cancun, 1_000_000_000_000, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Success));
+        // the frames at depth 0 to 1024 ran, the call at depth 1024 failed
+        assert_eq!(U256::from_be_bytes(&output), U256::from_u64(1025));
+    }
+
+    fn address(hex: &str) -> Address {
+        Address::from_slice(&decode_hex(hex).unwrap())
+    }
+
+    #[test]
+    fn create_addresses_follow_the_rlp_nonce_encoding() {
+        let sender = address("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        assert_eq!(create_address(&sender, 0), address("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"));
+        assert_eq!(create_address(&sender, 1), address("343c43a37d37dff08ae8c4a11544c718abb4fcf8"));
+        assert_eq!(create_address(&sender, 0x7f), address("06d9a77f5e4b311bae8d559db9cdb4df94104aa0"));
+        assert_eq!(create_address(&sender, 0x80), address("08e190dcb7b73f5fcdabb43e102215c83659a76d"));
+        assert_eq!(create_address(&sender, 0x100), address("3837c1ae70354f670550c746580199ac6a73cb0a"));
+        assert_eq!(create_address(&Address::zero(), 0), address("bd770416a3345f91e4b34576cb804a576fa48eb1"));
+    }
+
+    #[test]
+    fn create2_addresses_match_eip_1014() {
+        let cases = [
+            ("0000000000000000000000000000000000000000", "0", "00", "4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"),
+            ("deadbeef00000000000000000000000000000000", "0", "00", "b928f69bb1d91cd65274e3c79d8986362984fda3"),
+            ("deadbeef00000000000000000000000000000000", "000000000000000000000000feed000000000000000000000000000000000000", "00", "d04116cdd17bebe565eb2422f2497e06cc1c9833"),
+            ("0000000000000000000000000000000000000000", "0", "deadbeef", "70f2b2914a2a4b783faefb75f459a580616fcb5e"),
+            ("00000000000000000000000000000000deadbeef", "cafebabe", "deadbeef", "60f3f640a8508fc6a86d45df051962668e1e8ac7"),
+            ("00000000000000000000000000000000deadbeef", "cafebabe", "deadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef", "1d8bfdc5d46dc4f61d6b6115972536ebe6a8854c"),
+            ("0000000000000000000000000000000000000000", "0", "", "e33c0c7f7df4809055c3eba6c09cfe4baf1bd9e0"),
+        ];
+        for (sender, salt, initcode, expected) in cases.iter() {
+            let initcode = decode_hex(initcode).unwrap();
+            assert_eq!(create2_address(&address(sender), u256(salt), &initcode), address(expected));
+        }
+    }
+
+    #[test]
+    fn initcode_limit_and_word_gas_follow_eip_3860() {
+        // CREATE(0, 0, 49152) STOP, the initcode is zeros and deploys nothing
+        let code = "6200c00060006000f000";
+        let memory_gas = 3 * 1536 + 1536 * 1536 / 512;
+        assert_eq!(gas_used(code, Fork::Paris), 3 * 3 + 32000 + memory_gas);
+        assert_eq!(gas_used(code, Fork::Shanghai), 3 * 3 + 32000 + memory_gas + 2 * 1536);
+        // one byte over the limit halts from Shanghai only
+        let code = "6200c00160006000f000";
+        let result = execute(code, Fork::Shanghai, 1_000_000, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Halt(VmError::OutOfGas)));
+        let result = execute(code, Fork::Paris, 1_000_000, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Success));
+        // and so does initcode larger than 64KB
+        let code = "620100f060006000f000";
+        let result = execute(code, Fork::Paris, 10_000_000, MemoryStorage::new(), AccessSet::new());
+        assert!(matches!(result.status, VmStatus::Success));
+    }
+
+    #[test]
+    fn code_of_any_size_runs() {
+        // PUSH3 0x011000 JUMP, then JUMPDESTs past 64KB
+        let code = format!("6201100056{}", "5b".repeat(70000));
+        assert_eq!(gas_used(&code, Fork::Cancun), 3 + 8 + (70005 - 0x11000));
+        // a PUSH cut by the end of the code pushes zeros
+        assert_eq!(gas_used("7f01", Fork::Cancun), 3);
+        assert_eq!(gas_used("600161ab", Fork::Cancun), 6);
+    }
+
+    #[test]
+    fn exp_edge_cases() {
+        let zero = U256::default();
+        let one = U256::from_u64(1);
+        assert_eq!(exp_u256(zero, zero), one);
+        assert_eq!(exp_u256(u256(MAX), zero), one);
+        assert_eq!(exp_u256(zero, U256::from_u64(5)), zero);
+        assert_eq!(exp_u256(U256::from_u64(2), U256::from_u64(255)),
+            u256("0x8000000000000000000000000000000000000000000000000000000000000000"));
+        // results wrap around modulo 2^256
+        assert_eq!(exp_u256(U256::from_u64(2), U256::from_u64(256)), zero);
+        assert_eq!(exp_u256(u256(MAX), U256::from_u64(2)), one);
+        assert_eq!(exp_u256(U256::from_u64(3), U256::from_u64(0x100)),
+            u256("0xc7adeeb80d4fff81fed242815e55bc8375a205de07597d51d2105f2f0730f401"));
+        let exponent = u256("0x8000000000000000000000000000000000000000000000000000000000003039");
+        assert_eq!(exp_u256(U256::from_u64(7), exponent),
+            u256("0x1ff1b3b8cb58e38b9e654230f89eb7b25f0624ec21af297991afe6d609ff6ac7"));
+    }
+
+    #[test]
+    fn exp_byte_gas_follows_eip_160() {
+        // PUSH1 0xff PUSH1 2 EXP STOP
+        assert_eq!(gas_used("60ff60020a00", Fork::Frontier), 3 + 3 + 10 + 10);
+        assert_eq!(gas_used("60ff60020a00", Fork::Tangerine), 3 + 3 + 10 + 10);
+        assert_eq!(gas_used("60ff60020a00", Fork::Spurious), 3 + 3 + 10 + 50);
+        // PUSH2 0x0100 PUSH1 2 EXP STOP
+        assert_eq!(gas_used("61010060020a00", Fork::Frontier), 3 + 3 + 10 + 2 * 10);
+        assert_eq!(gas_used("61010060020a00", Fork::Cancun), 3 + 3 + 10 + 2 * 50);
+        // a zero exponent has no byte cost
+        assert_eq!(gas_used("600060020a00", Fork::Cancun), 3 + 3 + 10);
+    }
+}
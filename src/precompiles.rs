@@ -0,0 +1,615 @@
+// Copyright 2019 The Psyche Authors
+// This file is part of Psyche.
+//
+// Psyche is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Psyche is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Psyche. If not, see <http://www.gnu.org/licenses/>.
+
+
+use ethereum_types::Address;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use substrate_bn::{AffineG1, AffineG2, Fq, Fq2, Fr, G1, G2, Group, Gt};
+use crate::{bignum, num_words};
+use crate::keccak::keccak256;
+use crate::schedule::{Fork, Schedule};
+
+/// A contract implemented natively, at a fixed address
+pub struct Precompile {
+    /// Fork that introduced the contract
+    pub fork: Fork,
+    /// Returns the gas cost of a call with the given input
+    pub gas: fn(&[u8], &Schedule) -> u64,
+    /// Returns the output or `None` when the input is invalid, the call then
+    /// consumes all its gas
+    pub run: fn(&[u8]) -> Option<Vec<u8>>,
+}
+
+/// Precompiled contracts, the one at index `i` lives at address `i + 1`
+const PRECOMPILES: [Precompile; 9] = [
+    Precompile { fork: Fork::Frontier, gas: ecrecover_gas, run: ecrecover },
+    Precompile { fork: Fork::Frontier, gas: sha256_gas, run: sha256 },
+    Precompile { fork: Fork::Frontier, gas: ripemd160_gas, run: ripemd160 },
+    Precompile { fork: Fork::Frontier, gas: identity_gas, run: identity },
+    Precompile { fork: Fork::Byzantium, gas: modexp_gas, run: modexp },
+    Precompile { fork: Fork::Byzantium, gas: ec_add_gas, run: ec_add },
+    Precompile { fork: Fork::Byzantium, gas: ec_mul_gas, run: ec_mul },
+    Precompile { fork: Fork::Byzantium, gas: ec_pairing_gas, run: ec_pairing },
+    Precompile { fork: Fork::Istanbul, gas: blake2f_gas, run: blake2f },
+];
+
+/// Returns the precompiled contract at `address` if there is one in `fork`
+pub fn precompile(address: &Address, fork: Fork) -> Option<&'static Precompile> {
+    let bytes = address.as_bytes();
+    if bytes[..19].iter().any(|b| *b != 0) {
+        return None;
+    }
+    match bytes[19] as usize {
+        0 => None,
+        index => PRECOMPILES.get(index - 1).filter(|precompile| fork >= precompile.fork)
+    }
+}
+
+/// Returns the addresses of the precompiled contracts available in `fork`
+pub fn addresses(fork: Fork) -> Vec<Address> {
+    (0..PRECOMPILES.len())
+        .filter(|i| fork >= PRECOMPILES[*i].fork)
+        .map(|i| Address::from_low_u64_be(i as u64 + 1))
+        .collect()
+}
+
+/// Copies `input` into a buffer of `len` bytes, right padded with zeros
+fn padded(input: &[u8], len: usize) -> Vec<u8> {
+    read_padded(input, 0, len)
+}
+
+/// Reads `len` bytes of `input` at `offset`, bytes past its end are zeros
+fn read_padded(input: &[u8], offset: u64, len: usize) -> Vec<u8> {
+    let mut result = vec![0u8; len];
+    if offset < input.len() as u64 {
+        let input = &input[offset as usize..];
+        let count = std::cmp::min(input.len(), len);
+        result[..count].copy_from_slice(&input[..count]);
+    }
+    result
+}
+
+/// Reads a big endian word as a length, saturating at `u64::MAX`
+fn read_length(input: &[u8], offset: u64) -> u64 {
+    let word = read_padded(input, offset, 32);
+    if word[..24].iter().any(|b| *b != 0) {
+        return u64::MAX;
+    }
+    word[24..].iter().fold(0, |acc, b| (acc << 8) | (*b as u64))
+}
+
+fn ecrecover_gas(_input: &[u8], _schedule: &Schedule) -> u64 {
+    3000
+}
+
+/// Recovers the signer address of a message hash, the output is empty when
+/// the signature is invalid
+fn ecrecover(input: &[u8]) -> Option<Vec<u8>> {
+    let input = padded(input, 128);
+    // v is a full word holding 27 or 28
+    if input[32..63].iter().any(|b| *b != 0) || (input[63] != 27 && input[63] != 28) {
+        return Some(Vec::new());
+    }
+    let message = libsecp256k1::Message::parse_slice(&input[0..32]);
+    let recovery_id = libsecp256k1::RecoveryId::parse(input[63] - 27);
+    let signature = libsecp256k1::Signature::parse_standard_slice(&input[64..128]);
+    let public_key = match (message, recovery_id, signature) {
+        (Ok(message), Ok(recovery_id), Ok(signature)) => {
+            libsecp256k1::recover(&message, &signature, &recovery_id)
+        },
+        _ => return Some(Vec::new())
+    };
+    match public_key {
+        Ok(public_key) => {
+            let hash = keccak256(&public_key.serialize()[1..]);
+            let mut result = vec![0u8; 32];
+            result[12..].copy_from_slice(&hash[12..]);
+            Some(result)
+        },
+        Err(_) => Some(Vec::new())
+    }
+}
+
+fn sha256_gas(input: &[u8], _schedule: &Schedule) -> u64 {
+    60 + 12 * num_words(input.len() as u64)
+}
+
+fn sha256(input: &[u8]) -> Option<Vec<u8>> {
+    Some(Sha256::digest(input).to_vec())
+}
+
+fn ripemd160_gas(input: &[u8], _schedule: &Schedule) -> u64 {
+    600 + 120 * num_words(input.len() as u64)
+}
+
+/// RIPEMD-160 of the input, left padded to a word
+fn ripemd160(input: &[u8]) -> Option<Vec<u8>> {
+    let mut result = vec![0u8; 32];
+    result[12..].copy_from_slice(&Ripemd160::digest(input));
+    Some(result)
+}
+
+fn identity_gas(input: &[u8], _schedule: &Schedule) -> u64 {
+    15 + 3 * num_words(input.len() as u64)
+}
+
+fn identity(input: &[u8]) -> Option<Vec<u8>> {
+    Some(input.to_vec())
+}
+
+/// Lengths of the base, exponent and modulus of a MODEXP input
+fn modexp_lengths(input: &[u8]) -> (u64, u64, u64) {
+    (read_length(input, 0), read_length(input, 32), read_length(input, 64))
+}
+
+fn modexp_gas(input: &[u8], schedule: &Schedule) -> u64 {
+    let (base_len, exp_len, mod_len) = modexp_lengths(input);
+    // the bit length of the exponent comes from its first 32 bytes
+    let head_len = std::cmp::min(exp_len, 32);
+    let head = read_padded(input, 96u64.saturating_add(base_len), head_len as usize);
+    let head_bits = match head.iter().position(|b| *b != 0) {
+        Some(i) => ((head_len as usize - i) * 8) as u64 - head[i].leading_zeros() as u64,
+        None => 0
+    };
+    let adjusted_exp_len = if exp_len <= 32 {
+        head_bits.saturating_sub(1) as u128
+    } else {
+        8 * ((exp_len - 32) as u128) + head_bits.saturating_sub(1) as u128
+    };
+    let iterations = std::cmp::max(adjusted_exp_len, 1);
+    let x = std::cmp::max(base_len, mod_len) as u128;
+    let gas = if schedule.fork >= Fork::Berlin {
+        // EIP-2565
+        let words = x.div_ceil(8);
+        let complexity = words.saturating_mul(words);
+        std::cmp::max(200, complexity.saturating_mul(iterations) / 3)
+    } else {
+        // EIP-198
+        let complexity = if x <= 64 {
+            x * x
+        } else if x <= 1024 {
+            x * x / 4 + 96 * x - 3072
+        } else {
+            (x.saturating_mul(x) / 16).saturating_add(480 * x) - 199680
+        };
+        complexity.saturating_mul(iterations) / 20
+    };
+    std::cmp::min(gas, u64::MAX as u128) as u64
+}
+
+/// Modular exponentiation of arbitrary length integers (EIP-198)
+fn modexp(input: &[u8]) -> Option<Vec<u8>> {
+    let (base_len, exp_len, mod_len) = modexp_lengths(input);
+    if mod_len == 0 {
+        return Some(Vec::new());
+    }
+    // the gas cost bounds the base and modulus lengths when the modulus is
+    // not empty, not the exponent length as most of its bytes may be zeros
+    let exp_offset = 96u64.saturating_add(base_len);
+    let mod_offset = exp_offset.saturating_add(exp_len);
+    let base = read_padded(input, 96, base_len as usize);
+    // only read the exponent bytes present in the input
+    let exp_read = std::cmp::min(exp_len, (input.len() as u64).saturating_sub(exp_offset));
+    let exponent = read_padded(input, exp_offset, exp_read as usize);
+    let modulus = read_padded(input, mod_offset, mod_len as usize);
+    let result = bignum::mod_pow(
+        &bignum::from_be_bytes(&base),
+        &exponent,
+        exp_len - exp_read,
+        &bignum::from_be_bytes(&modulus)
+    );
+    Some(bignum::to_be_bytes(&result, mod_len as usize))
+}
+
+/// Reads a point of the alt_bn128 curve from 64 bytes, zeros being the point
+/// at infinity. Returns `None` for coordinates out of the field or points
+/// off the curve.
+fn read_g1(input: &[u8]) -> Option<G1> {
+    let x = Fq::from_slice(&input[0..32]).ok()?;
+    let y = Fq::from_slice(&input[32..64]).ok()?;
+    if x.is_zero() && y.is_zero() {
+        return Some(G1::zero());
+    }
+    AffineG1::new(x, y).ok().map(G1::from)
+}
+
+/// Reads a point of the twisted curve from 128 bytes, each coordinate being
+/// encoded with its imaginary part first
+fn read_g2(input: &[u8]) -> Option<G2> {
+    let fq = |offset: usize| Fq::from_slice(&input[offset..offset + 32]).ok();
+    let x = Fq2::new(fq(32)?, fq(0)?);
+    let y = Fq2::new(fq(96)?, fq(64)?);
+    if x.is_zero() && y.is_zero() {
+        return Some(G2::zero());
+    }
+    AffineG2::new(x, y).ok().map(G2::from)
+}
+
+fn write_g1(point: G1) -> Vec<u8> {
+    let mut result = vec![0u8; 64];
+    // the point at infinity is encoded as zeros
+    if let Some(point) = AffineG1::from_jacobian(point) {
+        point.x().to_big_endian(&mut result[0..32]).unwrap();
+        point.y().to_big_endian(&mut result[32..64]).unwrap();
+    }
+    result
+}
+
+fn ec_add_gas(_input: &[u8], schedule: &Schedule) -> u64 {
+    // EIP-1108 lowered the costs of the alt_bn128 precompiles
+    if schedule.fork >= Fork::Istanbul { 150 } else { 500 }
+}
+
+/// Point addition on alt_bn128 (EIP-196)
+fn ec_add(input: &[u8]) -> Option<Vec<u8>> {
+    let input = padded(input, 128);
+    let p = read_g1(&input[0..64])?;
+    let q = read_g1(&input[64..128])?;
+    Some(write_g1(p + q))
+}
+
+fn ec_mul_gas(_input: &[u8], schedule: &Schedule) -> u64 {
+    if schedule.fork >= Fork::Istanbul { 6000 } else { 40000 }
+}
+
+/// Scalar multiplication on alt_bn128 (EIP-196), the scalar may exceed the
+/// group order
+fn ec_mul(input: &[u8]) -> Option<Vec<u8>> {
+    let input = padded(input, 96);
+    let p = read_g1(&input[0..64])?;
+    let scalar = substrate_bn::arith::U256::from_slice(&input[64..96]).ok()?;
+    Some(write_g1(p * Fr::new_mul_factor(scalar)))
+}
+
+fn ec_pairing_gas(input: &[u8], schedule: &Schedule) -> u64 {
+    let pairs = (input.len() / 192) as u64;
+    if schedule.fork >= Fork::Istanbul {
+        45000 + 34000 * pairs
+    } else {
+        100000 + 80000 * pairs
+    }
+}
+
+/// Checks that the product of the pairings of the given points is one
+/// (EIP-197), the input being a list of (G1, G2) pairs of 192 bytes
+fn ec_pairing(input: &[u8]) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(192) {
+        return None;
+    }
+    let mut pairs: Vec<(G1, G2)> = Vec::with_capacity(input.len() / 192);
+    for chunk in input.chunks(192) {
+        pairs.push((read_g1(&chunk[0..64])?, read_g2(&chunk[64..192])?));
+    }
+    let mut result = vec![0u8; 32];
+    if substrate_bn::pairing_batch(&pairs) == Gt::one() {
+        result[31] = 1;
+    }
+    Some(result)
+}
+
+const BLAKE2F_INPUT_LEN: usize = 213;
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// The BLAKE2b mixing function
+fn blake2b_g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// The BLAKE2b compression function F with a given number of rounds
+fn blake2b_compress(rounds: u32, h: &mut [u64; 8], m: &[u64; 16], t: [u64; 2], last: bool) {
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= t[0];
+    v[13] ^= t[1];
+    if last {
+        v[14] = !v[14];
+    }
+    for i in 0..rounds as usize {
+        let s = &BLAKE2B_SIGMA[i % 10];
+        blake2b_g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        blake2b_g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        blake2b_g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        blake2b_g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        blake2b_g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        blake2b_g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        blake2b_g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        blake2b_g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+fn blake2f_gas(input: &[u8], _schedule: &Schedule) -> u64 {
+    // one gas per round, malformed inputs fail when run
+    if input.len() == BLAKE2F_INPUT_LEN {
+        u32::from_be_bytes([input[0], input[1], input[2], input[3]]) as u64
+    } else {
+        0
+    }
+}
+
+/// The BLAKE2b compression function (EIP-152), the input being the rounds,
+/// the state, the message block, the offset counters and the final flag
+fn blake2f(input: &[u8]) -> Option<Vec<u8>> {
+    if (input.len() != BLAKE2F_INPUT_LEN) || (input[212] > 1) {
+        return None;
+    }
+    let word = |offset: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&input[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    };
+    let rounds = u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
+    let mut h = [0u64; 8];
+    for (i, h) in h.iter_mut().enumerate() {
+        *h = word(4 + 8 * i);
+    }
+    let mut m = [0u64; 16];
+    for (i, m) in m.iter_mut().enumerate() {
+        *m = word(68 + 8 * i);
+    }
+    let t = [word(196), word(204)];
+    blake2b_compress(rounds, &mut h, &m, t, input[212] == 1);
+    let mut result = Vec::with_capacity(64);
+    for value in &h {
+        result.extend_from_slice(&value.to_le_bytes());
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        crate::decode_hex(text).unwrap()
+    }
+
+    const ECRECOVER_HASH: &str = "18c547e4f7b0f325ad1e56f57e26c745b09a3e503d86e00e5255ff7f715d3d1c";
+    const ECRECOVER_R: &str = "73b1693892219d736caba55bdb67216e485557ea6b6af75f37096c9aa6a5a75f";
+    /// Above n / 2, which transactions reject since Homestead but ecrecover
+    /// accepts
+    const ECRECOVER_HIGH_S: &str = "eeb940b1d03b21e36b0e47e79769f095fe2ab855bd91e3a38756b7d75a9c4549";
+
+    fn ecrecover_input(v: &str, s: &str) -> Vec<u8> {
+        hex(&format!("{}{:0>64}{}{}", ECRECOVER_HASH, v, ECRECOVER_R, s))
+    }
+
+    #[test]
+    fn ecrecover_recovers_the_signer() {
+        let expected = hex("000000000000000000000000a94f5374fce5edbc8e2a8697c15331677e6ebf0b");
+        assert_eq!(ecrecover(&ecrecover_input("1c", ECRECOVER_HIGH_S)), Some(expected));
+        let input = hex("38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e000000000000000000000000000000000000000000000000000000000000001b38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e789d1dd423d25f0772d2748d60f7e4b81bb14d086eba8e8e8efb6dcff8a4ae02");
+        let expected = hex("000000000000000000000000ceaccac640adf55b2028469bd36ba501f28b699d");
+        assert_eq!(ecrecover(&input), Some(expected));
+    }
+
+    #[test]
+    fn ecrecover_rejects_invalid_signatures() {
+        // v must be a full word holding 27 or 28
+        assert_eq!(ecrecover(&ecrecover_input("1d", ECRECOVER_HIGH_S)), Some(Vec::new()));
+        assert_eq!(ecrecover(&ecrecover_input("011c", ECRECOVER_HIGH_S)), Some(Vec::new()));
+        // s must be below the group order
+        let order = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+        assert_eq!(ecrecover(&ecrecover_input("1c", order)), Some(Vec::new()));
+        assert_eq!(ecrecover(&[]), Some(Vec::new()));
+    }
+
+    #[test]
+    fn hashes_of_the_empty_input() {
+        assert_eq!(sha256(&[]), Some(hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")));
+        assert_eq!(ripemd160(&[]), Some(hex("0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31")));
+        assert_eq!(identity(&[]), Some(Vec::new()));
+    }
+
+    #[test]
+    fn word_gas_rounds_up() {
+        let schedule = Schedule::from_fork(Fork::Cancun);
+        let costs = |len: usize| {
+            let input = vec![0u8; len];
+            (sha256_gas(&input, &schedule), ripemd160_gas(&input, &schedule), identity_gas(&input, &schedule))
+        };
+        assert_eq!(costs(0), (60, 600, 15));
+        assert_eq!(costs(1), (72, 720, 18));
+        assert_eq!(costs(32), (72, 720, 18));
+        assert_eq!(costs(33), (84, 840, 21));
+    }
+
+    /// The examples of EIP-198, 3 ** (p - 1) mod p and 0 ** (p - 1) mod p
+    const MODEXP_FERMAT: &str = "00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000002003fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2efffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+    const MODEXP_ZERO_BASE: &str = "000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000020fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2efffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+
+    #[test]
+    fn modexp_examples() {
+        let one = hex("0000000000000000000000000000000000000000000000000000000000000001");
+        assert_eq!(modexp(&hex(MODEXP_FERMAT)), Some(one));
+        assert_eq!(modexp(&hex(MODEXP_ZERO_BASE)), Some(vec![0u8; 32]));
+        // an empty modulus gives an empty output
+        assert_eq!(modexp(&hex(&MODEXP_FERMAT[..128])), Some(Vec::new()));
+    }
+
+    #[test]
+    fn modexp_gas_follows_eip_198_and_eip_2565() {
+        let byzantium = Schedule::from_fork(Fork::Byzantium);
+        let berlin = Schedule::from_fork(Fork::Berlin);
+        // 32 * 32 complexity and 255 iterations
+        assert_eq!(modexp_gas(&hex(MODEXP_FERMAT), &byzantium), 1024 * 255 / 20);
+        assert_eq!(modexp_gas(&hex(MODEXP_ZERO_BASE), &byzantium), 1024 * 255 / 20);
+        // 4 * 4 words squared and 255 iterations
+        assert_eq!(modexp_gas(&hex(MODEXP_FERMAT), &berlin), 16 * 255 / 3);
+        // EIP-2565 charges at least 200
+        assert_eq!(modexp_gas(&hex(&MODEXP_FERMAT[..128]), &berlin), 200);
+        assert_eq!(modexp_gas(&hex(&MODEXP_FERMAT[..128]), &byzantium), 0);
+    }
+
+    #[test]
+    fn modexp_with_exponent_past_the_input() {
+        // base 2, 4GB of exponent and a modulus of one byte past the input
+        let input = hex("00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000000000000102");
+        assert_eq!(modexp(&input), Some(vec![0u8]));
+        // huge lengths saturate the gas
+        let input = hex("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        assert_eq!(modexp_gas(&input, &Schedule::from_fork(Fork::Berlin)), u64::MAX);
+    }
+
+    #[test]
+    fn bignum_remainder() {
+        let rem = |a: &str, b: &str| {
+            let r = bignum::rem(&bignum::from_be_bytes(&hex(a)), &bignum::from_be_bytes(&hex(b)));
+            bignum::to_be_bytes(&r, 32)
+        };
+        // three limb divisors needing the add back and the qhat correction
+        assert_eq!(
+            rem("fffffffffffffffefffffffffffffffe00000001000000000000000100000000", "fffffffffffffffffffffffffffffffefffffffffffffffe"),
+            hex("0000000000000000ffffffffffffffff000000010000000000000000fffffffc"));
+        assert_eq!(
+            rem("7fffffffffffffff000000000000000000000000000000000000000000000001", "fffffffffffffffe80000000000000008000000000000000"),
+            hex("0000000000000000bffffffffffffffe40000000000000008000000000000001"));
+        // a divisor larger than the dividend
+        assert_eq!(rem("05", "0100000000000000000000"), hex("0000000000000000000000000000000000000000000000000000000000000005"));
+        assert_eq!(rem("0100000000000000000000", "0100000000000000000000"), vec![0u8; 32]);
+    }
+
+    #[test]
+    fn bignum_mod_pow() {
+        let base = bignum::from_be_bytes(&hex("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"));
+        let modulus = bignum::from_be_bytes(&hex("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffa5"));
+        let result = bignum::mod_pow(&base, &[0x01, 0x00, 0x01], 0, &modulus);
+        assert_eq!(bignum::to_be_bytes(&result, 40), hex("cb061718d29c84155e55c00416dc25c94a13de658564541cb425d3c3ac83a3e3397e8992c2d8b68c"));
+        // 3 ** 256 mod 7, the exponent being 0x01 and a zero byte
+        assert_eq!(bignum::mod_pow(&[3], &[1], 1, &[7]), vec![4]);
+        assert_eq!(bignum::mod_pow(&[3], &[], 0, &[7]), vec![1]);
+        assert_eq!(bignum::mod_pow(&[3], &[5], 0, &[1]), vec![0]);
+        assert_eq!(bignum::mod_pow(&[3], &[5], 0, &[0]), vec![0]);
+    }
+
+    const G1: &str = "00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000002";
+    const G1_DOUBLE: &str = "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd315ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4";
+    const G1_NEG: &str = "000000000000000000000000000000000000000000000000000000000000000130644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45";
+    /// The generator of the twisted curve, imaginary parts first
+    const G2: &str = "198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa";
+    const FIELD_MODULUS: &str = "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47";
+
+    #[test]
+    fn ec_add_and_mul() {
+        assert_eq!(ec_add(&hex(&format!("{}{}", G1, G1))), Some(hex(G1_DOUBLE)));
+        assert_eq!(ec_mul(&hex(&format!("{}{:0>64}", G1, "2"))), Some(hex(G1_DOUBLE)));
+        // zeros encode the point at infinity, also for a short input
+        assert_eq!(ec_add(&hex(G1)), Some(hex(G1)));
+        assert_eq!(ec_add(&hex(&format!("{}{}", G1, G1_NEG))), Some(vec![0u8; 64]));
+        assert_eq!(ec_add(&[]), Some(vec![0u8; 64]));
+        assert_eq!(ec_mul(&hex(G1)), Some(vec![0u8; 64]));
+    }
+
+    #[test]
+    fn ec_points_must_be_on_the_curve() {
+        let off_curve = format!("{:0>64}{:0>64}", "1", "3");
+        assert_eq!(ec_add(&hex(&off_curve)), None);
+        assert_eq!(ec_mul(&hex(&format!("{}{:0>64}", off_curve, "2"))), None);
+        // coordinates must be below the field modulus, even when equal to
+        // a valid one modulo p
+        let x_overflow = format!("{}{:0>64}", FIELD_MODULUS, "0");
+        assert_eq!(ec_add(&hex(&x_overflow)), None);
+        let y_overflow = format!("{:0>64}{:0>64}", "1", "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd49");
+        assert_eq!(ec_add(&hex(&y_overflow)), None);
+        let mut g2 = G2.to_string();
+        g2.replace_range(0..64, FIELD_MODULUS);
+        assert_eq!(ec_pairing(&hex(&format!("{}{}", G1, g2))), None);
+    }
+
+    #[test]
+    fn ec_pairing_checks() {
+        let one = hex(&format!("{:0>64}", "1"));
+        assert_eq!(ec_pairing(&[]), Some(one.clone()));
+        assert_eq!(ec_pairing(&hex(&format!("{}{}{}{}", G1, G2, G1_NEG, G2))), Some(one));
+        assert_eq!(ec_pairing(&hex(&format!("{}{}", G1, G2))), Some(vec![0u8; 32]));
+        assert_eq!(ec_pairing(&hex(G1)), None);
+    }
+
+    #[test]
+    fn ec_gas_dropped_in_istanbul() {
+        let byzantium = Schedule::from_fork(Fork::Byzantium);
+        let istanbul = Schedule::from_fork(Fork::Istanbul);
+        assert_eq!(ec_add_gas(&[], &byzantium), 500);
+        assert_eq!(ec_add_gas(&[], &istanbul), 150);
+        assert_eq!(ec_mul_gas(&[], &byzantium), 40000);
+        assert_eq!(ec_mul_gas(&[], &istanbul), 6000);
+        let two_pairs = vec![0u8; 384];
+        assert_eq!(ec_pairing_gas(&[], &byzantium), 100000);
+        assert_eq!(ec_pairing_gas(&two_pairs, &byzantium), 100000 + 2 * 80000);
+        assert_eq!(ec_pairing_gas(&[], &istanbul), 45000);
+        assert_eq!(ec_pairing_gas(&two_pairs, &istanbul), 45000 + 2 * 34000);
+    }
+
+    /// Input of the EIP-152 test vectors, the BLAKE2b state of "abc"
+    fn blake2f_input(rounds: u32, last_block: &str) -> Vec<u8> {
+        let h = "48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b";
+        let m = format!("{:0<256}", "616263");
+        let t = "03000000000000000000000000000000";
+        hex(&format!("{:08x}{}{}{}{}", rounds, h, m, t, last_block))
+    }
+
+    #[test]
+    fn blake2f_rejects_malformed_inputs() {
+        // vectors 0 to 3
+        let input = blake2f_input(12, "01");
+        assert_eq!(blake2f(&[]), None);
+        assert_eq!(blake2f(&input[1..]), None);
+        assert_eq!(blake2f(&[&[0], &input[..]].concat()), None);
+        assert_eq!(blake2f(&blake2f_input(12, "02")), None);
+        assert_eq!(blake2f_gas(&input[1..], &Schedule::from_fork(Fork::Istanbul)), 0);
+    }
+
+    #[test]
+    fn blake2f_vectors() {
+        // vectors 4 to 7
+        assert_eq!(blake2f(&blake2f_input(0, "01")), Some(hex("08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b")));
+        assert_eq!(blake2f(&blake2f_input(12, "01")), Some(hex("ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923")));
+        assert_eq!(blake2f(&blake2f_input(12, "00")), Some(hex("75ab69d3190a562c51aef8d88f1c2775876944407270c42c9844252c26d2875298743e7f6d5ea2f2d3e8d226039cd31b4e426ac4f2d3d666a610c2116fde4735")));
+        assert_eq!(blake2f(&blake2f_input(1, "01")), Some(hex("b63a380cb2897d521994a85234ee2c181b5f844d2c624c002677e9703449d2fba551b3a8333bcdf5f2f7e08993d53923de3d64fcc68c034e717b9293fed7a421")));
+        // vector 8 runs 2^32 - 1 rounds, only its gas is checked here
+        let schedule = Schedule::from_fork(Fork::Istanbul);
+        assert_eq!(blake2f_gas(&blake2f_input(0xffffffff, "01"), &schedule), 0xffffffff);
+        assert_eq!(blake2f_gas(&blake2f_input(12, "01"), &schedule), 12);
+    }
+}
@@ -14,7 +14,9 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with Psyche. If not, see <http://www.gnu.org/licenses/>.
 
-#[derive(Copy, Clone)]
+use std::str::FromStr;
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
 pub enum Fork {
     Frontier = 0,
     Thawing = 1,
@@ -25,11 +27,51 @@ pub enum Fork {
     Byzantium = 6,
     Constantinople = 7,
     Istanbul = 8,
+    Berlin = 9,
+    London = 10,
+    Paris = 11,
+    Shanghai = 12,
+    Cancun = 13,
+    /// Runs with the Cancun rules, the BLS12-381 precompiles (EIP-2537) are
+    /// not implemented
+    Prague = 14,
 }
 
 impl Fork {
+    /// Fork used when none is given, the most recent one
     pub fn default() -> Fork {
-        Fork::Frontier
+        Fork::latest()
+    }
+
+    /// Returns the most recent fully supported fork. Prague is not, calls to
+    /// its BLS12-381 precompiles would run as calls to empty accounts.
+    pub fn latest() -> Fork {
+        Fork::Cancun
+    }
+}
+
+impl FromStr for Fork {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Fork, String> {
+        match name.to_lowercase().as_str() {
+            "frontier" => Ok(Fork::Frontier),
+            "thawing" => Ok(Fork::Thawing),
+            "homestead" => Ok(Fork::Homestead),
+            "dao" => Ok(Fork::Dao),
+            "tangerine" => Ok(Fork::Tangerine),
+            "spurious" => Ok(Fork::Spurious),
+            "byzantium" => Ok(Fork::Byzantium),
+            "constantinople" => Ok(Fork::Constantinople),
+            "istanbul" => Ok(Fork::Istanbul),
+            "berlin" => Ok(Fork::Berlin),
+            "london" => Ok(Fork::London),
+            "paris" | "merge" => Ok(Fork::Paris),
+            "shanghai" => Ok(Fork::Shanghai),
+            "cancun" => Ok(Fork::Cancun),
+            "prague" => Ok(Fork::Prague),
+            _ => Err(format!("unknown fork '{}'", name))
+        }
     }
 }
 
@@ -47,6 +89,14 @@ pub enum Fee {
     Sha3,
     Copy,
     Blockhash,
+    ExtCode,
+    ExtCodeHash,
+    Sload,
+    Transient,
+    Log,
+    Call,
+    Create,
+    Selfdestruct,
 }
 
 impl Fee {
@@ -56,32 +106,108 @@ impl Fee {
     }
  }
 
+/// Gas metering rules of SSTORE
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum SstoreMetering {
+    /// Set or reset cost based on the current value only
+    Legacy,
+    /// Net gas metering (EIP-1283)
+    Net,
+    /// Net gas metering with a stipend sentry (EIP-2200)
+    NetSentry,
+}
+
 #[derive(Debug)]
 pub struct Schedule {
-    pub fees: [u32; 12],
-    pub memory_gas: u64
+    pub fork: Fork,
+    pub fees: [u32; 20],
+    pub memory_gas: u64,
+    pub exp_byte_gas: u64,
+    pub sha3_word_gas: u64,
+    pub log_topic_gas: u64,
+    pub log_data_gas: u64,
+    pub call_value_gas: u64,
+    pub call_stipend: u64,
+    pub new_account_gas: u64,
+    pub create_data_gas: u64,
+    /// Largest deployable code (EIP-170)
+    pub max_code_size: usize,
+    /// Largest initcode accepted by CREATE and CREATE2 (EIP-3860)
+    pub max_initcode_size: usize,
+    pub initcode_word_gas: u64,
+    pub selfdestruct_refund: i64,
+    /// SELFDESTRUCT only destroys accounts created in the same transaction
+    /// (EIP-6780)
+    pub selfdestruct_created_only: bool,
+    pub sstore_metering: SstoreMetering,
+    pub sstore_set_gas: u64,
+    pub sstore_reset_gas: u64,
+    pub sstore_clears_refund: i64,
+    pub sstore_sentry_gas: u64,
+    /// Track accessed addresses and slots (EIP-2929), static fees of
+    /// the accessing opcodes are then the warm costs
+    pub access_lists: bool,
+    pub cold_sload_gas: u64,
+    pub cold_account_access_gas: u64,
+    pub warm_storage_read_gas: u64
 }
 
 impl Schedule {
-    pub fn default() -> Schedule {
-        Schedule::from_fork(Fork::default())
-    }
-
     pub fn from_fork(fork: Fork) -> Schedule {
-        const COSTS: [[u32; 12]; 9] = [
-            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20], // Frontier
-            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20], // Thawing
-            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20], // Homestead
-            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20], // Dao
-            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20], // Tangerine
-            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20], // Spurious
-            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20], // Byzantium
-            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20], // Constantinople
-            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20], // Istanbul
+        const COSTS: [[u32; 20]; 15] = [
+            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20,  20,   0,  50,   0, 375,  40, 32000,    0], // Frontier
+            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20,  20,   0,  50,   0, 375,  40, 32000,    0], // Thawing
+            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20,  20,   0,  50,   0, 375,  40, 32000,    0], // Homestead
+            [0, 2, 3, 5, 8, 10,  20, 1, 10, 30, 3, 20,  20,   0,  50,   0, 375,  40, 32000,    0], // Dao
+            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20, 700,   0, 200,   0, 375, 700, 32000, 5000], // Tangerine
+            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20, 700,   0, 200,   0, 375, 700, 32000, 5000], // Spurious
+            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20, 700,   0, 200,   0, 375, 700, 32000, 5000], // Byzantium
+            [0, 2, 3, 5, 8, 10, 400, 1, 10, 30, 3, 20, 700, 400, 200,   0, 375, 700, 32000, 5000], // Constantinople
+            [0, 2, 3, 5, 8, 10, 700, 1, 10, 30, 3, 20, 700, 700, 800,   0, 375, 700, 32000, 5000], // Istanbul
+            [0, 2, 3, 5, 8, 10, 100, 1, 10, 30, 3, 20, 100, 100, 100,   0, 375, 100, 32000, 5000], // Berlin
+            [0, 2, 3, 5, 8, 10, 100, 1, 10, 30, 3, 20, 100, 100, 100,   0, 375, 100, 32000, 5000], // London
+            [0, 2, 3, 5, 8, 10, 100, 1, 10, 30, 3, 20, 100, 100, 100,   0, 375, 100, 32000, 5000], // Paris
+            [0, 2, 3, 5, 8, 10, 100, 1, 10, 30, 3, 20, 100, 100, 100,   0, 375, 100, 32000, 5000], // Shanghai
+            [0, 2, 3, 5, 8, 10, 100, 1, 10, 30, 3, 20, 100, 100, 100, 100, 375, 100, 32000, 5000], // Cancun
+            [0, 2, 3, 5, 8, 10, 100, 1, 10, 30, 3, 20, 100, 100, 100, 100, 375, 100, 32000, 5000], // Prague
         ];
         Schedule {
+            fork,
             fees: COSTS[fork as usize],
-            memory_gas: 3
+            memory_gas: 3,
+            // EIP-160 increased the cost per byte of exponent
+            exp_byte_gas: if fork >= Fork::Spurious { 50 } else { 10 },
+            sha3_word_gas: 6,
+            log_topic_gas: 375,
+            log_data_gas: 8,
+            call_value_gas: 9000,
+            call_stipend: 2300,
+            new_account_gas: 25000,
+            create_data_gas: 200,
+            max_code_size: if fork >= Fork::Spurious { 24576 } else { usize::MAX },
+            max_initcode_size: if fork >= Fork::Shanghai { 2 * 24576 } else { usize::MAX },
+            initcode_word_gas: if fork >= Fork::Shanghai { 2 } else { 0 },
+            // EIP-3529 removed the refund of SELFDESTRUCT
+            selfdestruct_refund: if fork >= Fork::London { 0 } else { 24000 },
+            selfdestruct_created_only: fork >= Fork::Cancun,
+            // EIP-1283 was only active in Constantinople, Petersburg removed it
+            sstore_metering: if fork >= Fork::Istanbul {
+                SstoreMetering::NetSentry
+            } else if fork == Fork::Constantinople {
+                SstoreMetering::Net
+            } else {
+                SstoreMetering::Legacy
+            },
+            sstore_set_gas: 20000,
+            // EIP-2929 moved the cold access part to `cold_sload_gas`
+            sstore_reset_gas: if fork >= Fork::Berlin { 2900 } else { 5000 },
+            // EIP-3529 reduced the refund for clearing a slot
+            sstore_clears_refund: if fork >= Fork::London { 4800 } else { 15000 },
+            sstore_sentry_gas: 2300,
+            access_lists: fork >= Fork::Berlin,
+            cold_sload_gas: 2100,
+            cold_account_access_gas: 2600,
+            warm_storage_read_gas: 100
         }
     }
 }
@@ -0,0 +1,384 @@
+// Copyright 2019 The Psyche Authors
+// This file is part of Psyche.
+//
+// Psyche is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Psyche is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with Psyche. If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::collections::{HashMap, HashSet};
+use ethereum_types::Address;
+use serde_json::Value;
+use crate::block::parse_u256;
+use crate::keccak::keccak256;
+use crate::{decode_hex, U256};
+
+#[derive(Clone)]
+pub struct Account {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+}
+
+impl Account {
+    pub fn new(balance: U256, nonce: u64, code: Vec<u8>) -> Account {
+        Account { balance, nonce, code }
+    }
+
+    pub fn default() -> Account {
+        Account::new(U256::default(), 0, Vec::new())
+    }
+
+    /// Returns true if the account has no code, a zero nonce and a zero
+    /// balance (EIP-161)
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty() & (self.nonce == 0) & self.balance.is_zero()
+    }
+}
+
+/// Accounts the VM can query and modify, implemented by the embedding code
+pub trait AccountState {
+    /// Returns the account at `address` or `None` if it does not exist
+    fn account(&self, address: &Address) -> Option<&Account>;
+
+    /// Returns the account at `address` for modification, creating an empty
+    /// one if it does not exist
+    fn account_mut(&mut self, address: &Address) -> &mut Account;
+
+    /// Returns a checkpoint to revert to when a frame fails
+    fn checkpoint(&self) -> usize;
+
+    /// Undoes the modifications made since `checkpoint`
+    fn revert(&mut self, checkpoint: usize);
+
+    /// Records that the account at `address` was created by the transaction
+    fn mark_created(&mut self, address: &Address);
+
+    fn is_created(&self, address: &Address) -> bool;
+
+    /// Schedules the account at `address` for destruction at the end of the
+    /// transaction
+    fn mark_destructed(&mut self, address: &Address);
+
+    fn is_destructed(&self, address: &Address) -> bool;
+
+    /// Removes the accounts scheduled for destruction, called at the end of
+    /// the transaction. Returns their addresses.
+    fn apply_destructs(&mut self) -> Vec<Address>;
+
+    fn exists(&self, address: &Address) -> bool {
+        self.account(address).is_some()
+    }
+
+    /// Returns true if the account does not exist or is empty (EIP-161)
+    fn is_empty(&self, address: &Address) -> bool {
+        match self.account(address) {
+            Some(account) => account.is_empty(),
+            None => true
+        }
+    }
+
+    fn balance(&self, address: &Address) -> U256 {
+        match self.account(address) {
+            Some(account) => account.balance,
+            None => U256::default()
+        }
+    }
+
+    /// Returns the code of the account, empty if the account does not exist
+    fn code(&self, address: &Address) -> &[u8] {
+        match self.account(address) {
+            Some(account) => &account.code,
+            None => &[]
+        }
+    }
+
+    /// Returns the hash of the account code or zero if the account does not
+    /// exist or is empty (EIP-1052)
+    fn code_hash(&self, address: &Address) -> U256 {
+        match self.account(address) {
+            Some(account) if !account.is_empty() => {
+                U256::from_be_bytes(&keccak256(&account.code))
+            },
+            _ => U256::default()
+        }
+    }
+}
+
+/// A reversible change of `MemoryState`
+enum StateChange {
+    /// Previous version of a modified account
+    Account(Address, Option<Account>),
+    Created(Address),
+    Destructed(Address),
+}
+
+/// A simple account state held in memory
+pub struct MemoryState {
+    accounts: HashMap<Address, Account>,
+    created: HashSet<Address>,
+    destructed: HashSet<Address>,
+    /// Changes made by the transaction, to revert frames
+    journal: Vec<StateChange>,
+}
+
+impl MemoryState {
+    pub fn new() -> MemoryState {
+        MemoryState {
+            accounts: HashMap::new(),
+            created: HashSet::new(),
+            destructed: HashSet::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, address: Address, account: Account) {
+        self.accounts.insert(address, account);
+    }
+}
+
+impl AccountState for MemoryState {
+    fn account(&self, address: &Address) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    fn account_mut(&mut self, address: &Address) -> &mut Account {
+        let previous = self.accounts.get(address).cloned();
+        self.journal.push(StateChange::Account(*address, previous));
+        self.accounts.entry(*address).or_insert_with(Account::default)
+    }
+
+    fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    fn revert(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            match self.journal.pop().unwrap() {
+                StateChange::Account(address, Some(account)) => {
+                    self.accounts.insert(address, account);
+                },
+                StateChange::Account(address, None) => {
+                    self.accounts.remove(&address);
+                },
+                StateChange::Created(address) => {
+                    self.created.remove(&address);
+                },
+                StateChange::Destructed(address) => {
+                    self.destructed.remove(&address);
+                }
+            }
+        }
+    }
+
+    fn mark_created(&mut self, address: &Address) {
+        if self.created.insert(*address) {
+            self.journal.push(StateChange::Created(*address));
+        }
+    }
+
+    fn is_created(&self, address: &Address) -> bool {
+        self.created.contains(address)
+    }
+
+    fn mark_destructed(&mut self, address: &Address) {
+        if self.destructed.insert(*address) {
+            self.journal.push(StateChange::Destructed(*address));
+        }
+    }
+
+    fn is_destructed(&self, address: &Address) -> bool {
+        self.destructed.contains(address)
+    }
+
+    fn apply_destructs(&mut self) -> Vec<Address> {
+        let addresses: Vec<Address> = self.destructed.drain().collect();
+        for address in &addresses {
+            self.accounts.remove(address);
+        }
+        self.created.clear();
+        self.journal.clear();
+        addresses
+    }
+}
+
+/// Contract storage, implemented by the embedding code
+pub trait Storage {
+    /// Returns the current value of `key` in the storage of `address`
+    fn load(&self, address: &Address, key: &U256) -> U256;
+
+    /// Returns the value of `key` at the start of the transaction, needed by
+    /// net gas metering (EIP-1283, EIP-2200)
+    fn original(&self, address: &Address, key: &U256) -> U256;
+
+    fn store(&mut self, address: &Address, key: &U256, value: U256);
+
+    /// Returns a checkpoint to revert to when a frame fails
+    fn checkpoint(&self) -> usize;
+
+    /// Undoes the stores made since `checkpoint`
+    fn revert(&mut self, checkpoint: usize);
+
+    /// Removes all the slots of a destroyed account
+    fn clear(&mut self, address: &Address);
+}
+
+/// A simple storage held in memory, unset slots are zero
+pub struct MemoryStorage {
+    original: HashMap<(Address, U256), U256>,
+    current: HashMap<(Address, U256), U256>,
+    /// Previous values of the stored slots, to revert frames
+    journal: Vec<((Address, U256), U256)>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage {
+            original: HashMap::new(),
+            current: HashMap::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    /// Sets the value of a slot before the transaction
+    pub fn insert(&mut self, address: Address, key: U256, value: U256) {
+        self.original.insert((address, key), value);
+        self.current.insert((address, key), value);
+    }
+
+    /// Sets the slots of `address` from a JSON object mapping slots to
+    /// values, e.g. `{"0x00": "0x2a"}`
+    pub fn insert_json(&mut self, address: Address, value: &Value) -> Result<(), String> {
+        let object = value.as_object().ok_or("expected a JSON object")?;
+        for (key, value) in object {
+            let slot = parse_u256(&Value::String(key.clone()))
+                .ok_or(format!("invalid slot {}", key))?;
+            let value = parse_u256(value).ok_or(format!("invalid value of slot {}", key))?;
+            self.insert(address, slot, value);
+        }
+        Ok(())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self, address: &Address, key: &U256) -> U256 {
+        match self.current.get(&(*address, *key)) {
+            Some(value) => *value,
+            None => U256::default()
+        }
+    }
+
+    fn original(&self, address: &Address, key: &U256) -> U256 {
+        match self.original.get(&(*address, *key)) {
+            Some(value) => *value,
+            None => U256::default()
+        }
+    }
+
+    fn store(&mut self, address: &Address, key: &U256, value: U256) {
+        let previous = self.load(address, key);
+        self.journal.push(((*address, *key), previous));
+        self.current.insert((*address, *key), value);
+    }
+
+    fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    fn revert(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            let (slot, value) = self.journal.pop().unwrap();
+            self.current.insert(slot, value);
+        }
+    }
+
+    fn clear(&mut self, address: &Address) {
+        self.original.retain(|(owner, _), _| owner != address);
+        self.current.retain(|(owner, _), _| owner != address);
+    }
+}
+
+/// Parses accounts in the `pre` format of the Ethereum state tests, e.g.
+/// `{"0x..": {"balance": "0x..", "nonce": "0x..", "code": "0x..", "storage": {}}}`
+pub fn parse_alloc(json: &str) -> Result<(MemoryState, MemoryStorage), String> {
+    let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let object = value.as_object().ok_or("expected a JSON object")?;
+    let mut state = MemoryState::new();
+    let mut storage = MemoryStorage::new();
+    for (key, value) in object {
+        let address = key.trim_start_matches("0x").parse::<Address>()
+            .map_err(|_| format!("invalid address {}", key))?;
+        let field = |name: &str| -> Result<U256, String> {
+            match value.get(name) {
+                Some(value) => parse_u256(value).ok_or(format!("invalid {} of {}", name, key)),
+                None => Ok(U256::default())
+            }
+        };
+        let balance = field("balance")?;
+        let nonce = field("nonce")?;
+        if !nonce.le_u64() {
+            return Err(format!("invalid nonce of {}", key));
+        }
+        let code = match value.get("code").and_then(Value::as_str) {
+            Some(code) => decode_hex(code.trim_start_matches("0x"))
+                .map_err(|_| format!("invalid code of {}", key))?,
+            None => Vec::new()
+        };
+        state.insert(address, Account::new(balance, nonce.low_u64(), code));
+        if let Some(slots) = value.get("storage") {
+            storage.insert_json(address, slots)?;
+        }
+    }
+    Ok((state, storage))
+}
+
+/// Transient storage (EIP-1153), discarded at the end of the transaction
+pub struct TransientStorage {
+    values: HashMap<(Address, U256), U256>,
+    /// Previous values of the stored slots, to revert frames
+    journal: Vec<((Address, U256), U256)>,
+}
+
+impl TransientStorage {
+    pub fn new() -> TransientStorage {
+        TransientStorage {
+            values: HashMap::new(),
+            journal: Vec::new(),
+        }
+    }
+
+    pub fn load(&self, address: &Address, key: &U256) -> U256 {
+        match self.values.get(&(*address, *key)) {
+            Some(value) => *value,
+            None => U256::default()
+        }
+    }
+
+    pub fn store(&mut self, address: &Address, key: &U256, value: U256) {
+        let previous = self.load(address, key);
+        self.journal.push(((*address, *key), previous));
+        self.values.insert((*address, *key), value);
+    }
+
+    /// Returns a checkpoint to revert to when a frame fails
+    pub fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// Undoes the stores made since `checkpoint`
+    pub fn revert(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            let (slot, value) = self.journal.pop().unwrap();
+            self.values.insert(slot, value);
+        }
+    }
+}